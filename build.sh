@@ -19,7 +19,7 @@ fi
 RUST_TARGET="$ARCH-unknown-none-gnu"
 echo "Target: $RUST_TARGET"
 
-cargo build $RUST_XFLAGS --target ./$RUST_TARGET.json
+cargo build $RUST_XFLAGS -Zbuild-std=core,alloc --target ./$RUST_TARGET.json
 
 APPNAME="tilers"
 LIBNAME="lib$APPNAME.a"
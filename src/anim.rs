@@ -0,0 +1,126 @@
+//! Animation timing: slide durations and easing curves
+use crate::timer;
+
+/// Progress of an animation when it is complete
+/// (fixed point, 0 being the start)
+pub const PROGRESS_MAX: u32 = 1024;
+
+/// Run an animation for the given duration,
+/// calling `step` once per iteration with the progress so far
+/// (the last call being at `PROGRESS_MAX`).
+pub fn animate(duration: u32, mut step: impl FnMut(u32)) {
+    let start = timer::millis();
+    loop {
+        let elapsed = timer::millis().wrapping_sub(start) as u64;
+        let t = (elapsed * PROGRESS_MAX as u64 / duration.max(1) as u64).min(PROGRESS_MAX as u64)
+            as u32;
+        step(t);
+        if t == PROGRESS_MAX {
+            break;
+        }
+    }
+}
+
+/// How long it takes for a tile to slide into the empty slot
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum SlideSpeed {
+    Slow,
+    Normal,
+    Fast,
+}
+
+impl SlideSpeed {
+    /// The duration of a slide in milliseconds,
+    /// regardless of the size of the tiles
+    pub fn millis(self) -> u32 {
+        match self {
+            SlideSpeed::Slow => 250,
+            SlideSpeed::Normal => 150,
+            SlideSpeed::Fast => 80,
+        }
+    }
+
+    /// The name of the slide speed, as shown in the options
+    pub fn name(self) -> &'static str {
+        match self {
+            SlideSpeed::Slow => "Slow",
+            SlideSpeed::Normal => "Normal",
+            SlideSpeed::Fast => "Fast",
+        }
+    }
+
+    /// The slide speed after this one in the options
+    pub fn next(self) -> Self {
+        match self {
+            SlideSpeed::Slow => SlideSpeed::Normal,
+            SlideSpeed::Normal => SlideSpeed::Fast,
+            SlideSpeed::Fast => SlideSpeed::Slow,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(SlideSpeed::Slow),
+            1 => Some(SlideSpeed::Normal),
+            2 => Some(SlideSpeed::Fast),
+            _ => None,
+        }
+    }
+}
+
+/// The curve followed by a sliding tile over time
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Easing {
+    /// constant speed
+    Linear,
+    /// start fast, then slow down
+    EaseOut,
+    /// accelerate, then slow down
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map the progress in time of an animation
+    /// to the progress in space (both from 0 to `PROGRESS_MAX`).
+    pub fn apply(self, t: u32) -> u32 {
+        let t = t.min(PROGRESS_MAX) as u64;
+        let max = PROGRESS_MAX as u64;
+        let p = match self {
+            Easing::Linear => t,
+            // 1 - (1 - t)^2
+            Easing::EaseOut => max - (max - t) * (max - t) / max,
+            // smoothstep: 3t^2 - 2t^3
+            Easing::EaseInOut => t * t * (3 * max - 2 * t) / (max * max),
+        };
+        p as u32
+    }
+
+    /// The name of the easing curve, as shown in the options
+    pub fn name(self) -> &'static str {
+        match self {
+            Easing::Linear => "Linear",
+            Easing::EaseOut => "Ease out",
+            Easing::EaseInOut => "Ease in-out",
+        }
+    }
+
+    /// The easing curve after this one in the options
+    pub fn next(self) -> Self {
+        match self {
+            Easing::Linear => Easing::EaseOut,
+            Easing::EaseOut => Easing::EaseInOut,
+            Easing::EaseInOut => Easing::Linear,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Easing::Linear),
+            1 => Some(Easing::EaseOut),
+            2 => Some(Easing::EaseInOut),
+            _ => None,
+        }
+    }
+}
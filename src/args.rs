@@ -0,0 +1,259 @@
+//! The command line, in DOS style:
+//!
+//! ```none
+//! TILERS /SEED:123 /MODE:time /NOSOUND
+//! ```
+//!
+//! Options may also start with `-` and are case insensitive.
+//! The bare words of earlier versions
+//! (`nosound`, `timeattack`, `movelimit` and a numeric seed)
+//! are still accepted.
+use alloc::string::String;
+use dos_x::println;
+
+use crate::{GameMode, Overrides, SeedPolicy};
+
+/// The smallest number of columns or rows of a grid
+const MIN_GRID: u8 = 2;
+
+/// The largest number of columns or rows of a grid
+const MAX_GRID: u8 = 10;
+
+/// What the command line asks for
+pub enum Command {
+    /// show the usage and leave
+    Help,
+    /// play with the given overrides
+    Play(Overrides),
+}
+
+/// Something wrong with the command line
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Error {
+    /// an argument which is not a known option
+    Unknown(String),
+    /// an option given without the value it needs
+    MissingValue(&'static str),
+    /// an option given a value it cannot take
+    InvalidValue(&'static str, String),
+}
+
+impl Error {
+    /// Print the error (in text mode)
+    pub fn print(&self) {
+        match self {
+            Error::Unknown(arg) => println!("Unknown option: {}", arg),
+            Error::MissingValue(option) => println!("Missing value for /{}", option),
+            Error::InvalidValue(option, value) => {
+                println!("Invalid value for /{}: {}", option, value)
+            }
+        }
+    }
+}
+
+/// Print how to use the command line (in text mode)
+pub fn print_usage() {
+    println!("Usage: TILERS [options]");
+    println!("  /?           Show this help");
+    println!("  /SEED:n      Shuffle the tiles with seed n (or RANDOM)");
+    println!("  /LEVEL:n     Start right away at level n, if unlocked");
+    println!("  /GRID:CxR    Play every level with C columns and R rows");
+    println!("  /MODE:m      Play in CLASSIC, TIME or MOVES mode");
+    println!("  /NOSOUND     Turn off the PC speaker");
+    println!("  /PATH:dir    Look for level pictures in dir");
+}
+
+/// Parse the command line arguments (without the program name).
+pub fn parse<'a>(args: impl IntoIterator<Item = &'a [u8]>) -> Result<Command, Error> {
+    let mut overrides = Overrides::default();
+    for arg in args {
+        let Ok(arg) = core::str::from_utf8(arg) else {
+            return Err(Error::Unknown(String::from_utf8_lossy(arg).into()));
+        };
+        let Some(option) = arg.strip_prefix(['/', '-']) else {
+            parse_bare_word(arg, &mut overrides)?;
+            continue;
+        };
+        let (name, value) = match option.split_once(':') {
+            Some((name, value)) => (name, Some(value)),
+            None => (option, None),
+        };
+        // an option which takes a value
+        let required = |option: &'static str| match value {
+            Some(value) if !value.is_empty() => Ok(value),
+            _ => Err(Error::MissingValue(option)),
+        };
+        let invalid =
+            |option: &'static str| Error::InvalidValue(option, value.unwrap_or("").into());
+
+        if name == "?" || name.eq_ignore_ascii_case("h") || name.eq_ignore_ascii_case("help") {
+            return Ok(Command::Help);
+        } else if name.eq_ignore_ascii_case("nosound") && value.is_none() {
+            overrides.sound = Some(false);
+        } else if name.eq_ignore_ascii_case("seed") {
+            let value = required("SEED")?;
+            overrides.seed = Some(if value.eq_ignore_ascii_case("random") {
+                SeedPolicy::Random
+            } else {
+                SeedPolicy::Fixed(value.parse().map_err(|_| invalid("SEED"))?)
+            });
+        } else if name.eq_ignore_ascii_case("level") {
+            let level: u8 = required("LEVEL")?.parse().map_err(|_| invalid("LEVEL"))?;
+            if level == 0 {
+                return Err(invalid("LEVEL"));
+            }
+            overrides.level = Some(level - 1);
+        } else if name.eq_ignore_ascii_case("grid") {
+            overrides.grid = Some(parse_grid(required("GRID")?).ok_or_else(|| invalid("GRID"))?);
+        } else if name.eq_ignore_ascii_case("mode") {
+            overrides.mode = Some(parse_mode(required("MODE")?).ok_or_else(|| invalid("MODE"))?);
+        } else if name.eq_ignore_ascii_case("path") {
+            overrides.path = Some(required("PATH")?.into());
+        } else {
+            return Err(Error::Unknown(arg.into()));
+        }
+    }
+    Ok(Command::Play(overrides))
+}
+
+/// Parse one of the arguments of earlier versions, given without a slash.
+fn parse_bare_word(arg: &str, overrides: &mut Overrides) -> Result<(), Error> {
+    if arg.eq_ignore_ascii_case("nosound") {
+        overrides.sound = Some(false);
+    } else if arg.eq_ignore_ascii_case("timeattack") {
+        overrides.mode = Some(GameMode::TimeAttack);
+    } else if arg.eq_ignore_ascii_case("movelimit") {
+        overrides.mode = Some(GameMode::MoveLimit);
+    } else if let Ok(seed) = arg.parse() {
+        overrides.seed = Some(SeedPolicy::Fixed(seed));
+    } else {
+        return Err(Error::Unknown(arg.into()));
+    }
+    Ok(())
+}
+
+/// Parse a game mode, by its short or full name.
+fn parse_mode(value: &str) -> Option<GameMode> {
+    let is = |names: &[&str]| names.iter().any(|n| n.eq_ignore_ascii_case(value));
+    if is(&["classic"]) {
+        Some(GameMode::Classic)
+    } else if is(&["time", "timeattack"]) {
+        Some(GameMode::TimeAttack)
+    } else if is(&["moves", "movelimit"]) {
+        Some(GameMode::MoveLimit)
+    } else {
+        None
+    }
+}
+
+/// Parse a grid size such as `5x4` (columns by rows).
+fn parse_grid(value: &str) -> Option<(u8, u8)> {
+    let (cols, rows) = value.split_once(['x', 'X'])?;
+    let cols: u8 = cols.parse().ok()?;
+    let rows: u8 = rows.parse().ok()?;
+    let range = MIN_GRID..=MAX_GRID;
+    (range.contains(&cols) && range.contains(&rows)).then_some((cols, rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &[&str]) -> Result<Command, Error> {
+        parse(args.iter().map(|arg| arg.as_bytes()))
+    }
+
+    fn overrides(args: &[&str]) -> Overrides {
+        match parse_str(args) {
+            Ok(Command::Play(overrides)) => overrides,
+            Ok(Command::Help) => panic!("unexpected help for {:?}", args),
+            Err(e) => panic!("unexpected error for {:?}: {:?}", args, e),
+        }
+    }
+
+    #[test]
+    fn valid_switches() {
+        let o = overrides(&[
+            "/SEED:123",
+            "/LEVEL:4",
+            "/GRID:5x4",
+            "/MODE:time",
+            "/NOSOUND",
+            "/PATH:C:\\PICS",
+        ]);
+        assert_eq!(o.seed, Some(SeedPolicy::Fixed(123)));
+        assert_eq!(o.level, Some(3));
+        assert_eq!(o.grid, Some((5, 4)));
+        assert_eq!(o.mode, Some(GameMode::TimeAttack));
+        assert_eq!(o.sound, Some(false));
+        assert_eq!(o.path.as_deref(), Some("C:\\PICS"));
+
+        assert_eq!(overrides(&["-seed:random"]).seed, Some(SeedPolicy::Random));
+        assert!(matches!(parse_str(&["/?"]), Ok(Command::Help)));
+        assert!(matches!(parse_str(&["-help"]), Ok(Command::Help)));
+    }
+
+    #[test]
+    fn switches_are_case_insensitive() {
+        let o = overrides(&["/nosound", "/Mode:MOVES", "/grid:3X2", "/Seed:Random"]);
+        assert_eq!(o.sound, Some(false));
+        assert_eq!(o.mode, Some(GameMode::MoveLimit));
+        assert_eq!(o.grid, Some((3, 2)));
+        assert_eq!(o.seed, Some(SeedPolicy::Random));
+    }
+
+    #[test]
+    fn bare_words_of_earlier_versions() {
+        let o = overrides(&["NoSound", "timeattack", "42"]);
+        assert_eq!(o.sound, Some(false));
+        assert_eq!(o.mode, Some(GameMode::TimeAttack));
+        assert_eq!(o.seed, Some(SeedPolicy::Fixed(42)));
+    }
+
+    #[test]
+    fn grid_out_of_range() {
+        for grid in ["1x4", "11x4", "4x1", "4x11", "0x0", "4", "4x", "x4", "axb"] {
+            let arg = alloc::format!("/GRID:{}", grid);
+            assert_eq!(
+                parse_str(&[&arg]).err(),
+                Some(Error::InvalidValue("GRID", grid.into()))
+            );
+        }
+        assert_eq!(overrides(&["/GRID:2x10"]).grid, Some((2, 10)));
+    }
+
+    #[test]
+    fn invalid_and_missing_values() {
+        assert_eq!(
+            parse_str(&["/LEVEL:0"]).err(),
+            Some(Error::InvalidValue("LEVEL", "0".into()))
+        );
+        assert_eq!(
+            parse_str(&["/MODE:fast"]).err(),
+            Some(Error::InvalidValue("MODE", "fast".into()))
+        );
+        assert_eq!(
+            parse_str(&["/SEED:abc"]).err(),
+            Some(Error::InvalidValue("SEED", "abc".into()))
+        );
+        assert_eq!(
+            parse_str(&["/SEED"]).err(),
+            Some(Error::MissingValue("SEED"))
+        );
+        assert_eq!(
+            parse_str(&["/PATH:"]).err(),
+            Some(Error::MissingValue("PATH"))
+        );
+    }
+
+    #[test]
+    fn unknown_switches() {
+        for arg in ["/FAST", "-x", "/NOSOUND:yes", "fast"] {
+            assert_eq!(parse_str(&[arg]).err(), Some(Error::Unknown(arg.into())));
+        }
+        assert_eq!(
+            parse(core::iter::once(&b"/\xff"[..])).err(),
+            Some(Error::Unknown("/\u{fffd}".into()))
+        );
+    }
+}
@@ -3,6 +3,8 @@ use dos_x::djgpp::{
     pc::{inportb, outportb},
 };
 
+use crate::timer;
+
 static mut NO_SOUND: bool = false;
 
 // Hz
@@ -15,10 +17,10 @@ macro_rules! const_note {
     };
 }
 
-/// disable sound
-pub fn sound_off() {
+/// enable or disable sound
+pub fn set_sound(on: bool) {
     unsafe {
-        NO_SOUND = true;
+        NO_SOUND = !on;
     }
 }
 
@@ -26,74 +28,111 @@ pub fn is_sound_on() -> bool {
     unsafe { !NO_SOUND }
 }
 
+// setting up a few constants for note countdowns
+const_note!(NOTE_A5, 880);
+const_note!(NOTE_CS6, 1109);
+const_note!(NOTE_D6, 1175);
+const_note!(NOTE_E6, 1328);
+const_note!(NOTE_GS6, 1661);
+const_note!(NOTE_A6, 1760);
+const_note!(NOTE_B6, 1975);
+
+const NOTE_VOID: u16 = 3;
+
+/// The notes of the tune (countdown and duration in milliseconds).
+///
+/// String Quintet in E Major, Op. 11, No. 5, G. 275: III. Minuetto by Boccherini
+const TUNE: [(u16, u32); 18] = [
+    (NOTE_A6, 75),
+    (NOTE_B6, 75),
+    (NOTE_A6, 140),
+    (NOTE_GS6, 140),
+    (NOTE_A6, 140),
+    (NOTE_B6, 140),
+    (NOTE_A6, 140),
+    (NOTE_VOID, 140),
+    (NOTE_A5, 280),
+    (NOTE_VOID, 280),
+    (NOTE_CS6, 280),
+    (NOTE_VOID, 280),
+    (NOTE_E6, 140),
+    (NOTE_VOID, 140),
+    (NOTE_E6, 280),
+    (NOTE_D6, 140),
+    (NOTE_VOID, 140),
+    (NOTE_D6, 280),
+];
+
 /// Play a tune using PC speaker
 pub fn play_tune() {
-    if unsafe { NO_SOUND } {
+    if !is_sound_on() {
         return;
     }
-
-    // setting up a few constants for note countdowns
-    const_note!(NOTE_A5, 880);
-    const_note!(NOTE_CS6, 1109);
-    const_note!(NOTE_D6, 1175);
-    const_note!(NOTE_E6, 1328);
-    const_note!(NOTE_GS6, 1661);
-    const_note!(NOTE_A6, 1760);
-    const_note!(NOTE_B6, 1975);
-
-    const NOTE_VOID: u16 = 3;
-
-    // use PC speaker
+    let mut tune = Tune::start();
+    while tune.update() {}
     unsafe {
-        pc_speaker_on();
-
-        // String Quintet in E Major, Op. 11, No. 5, G. 275: III. Minuetto by Boccherini
-        play_note(NOTE_A6);
-        delay(75);
-        play_note(NOTE_B6);
-        delay(75);
-        play_note(NOTE_A6);
-        delay(140);
-        play_note(NOTE_GS6);
-        delay(140);
-        play_note(NOTE_A6);
-        delay(140);
-        play_note(NOTE_B6);
-        delay(140);
-        play_note(NOTE_A6);
-        delay(140);
-
-        play_note(NOTE_VOID);
         delay(140);
+    }
+}
 
-        play_note(NOTE_A5);
-        delay(280);
-
-        play_note(NOTE_VOID);
-        delay(280);
-
-        play_note(NOTE_CS6);
-        delay(280);
+/// The tune played in the background,
+/// one note at a time as `update` is called
+pub struct Tune {
+    /// the index of the next note
+    next: usize,
+    /// when the next note is due, in milliseconds
+    next_at: u32,
+    playing: bool,
+}
 
-        play_note(NOTE_VOID);
-        delay(280);
+impl Tune {
+    /// Start playing the tune (unless sound is off).
+    pub fn start() -> Self {
+        let playing = is_sound_on();
+        if playing {
+            unsafe {
+                pc_speaker_on();
+            }
+        }
+        Tune {
+            next: 0,
+            next_at: timer::millis(),
+            playing,
+        }
+    }
 
-        play_note(NOTE_E6);
-        delay(140);
-        play_note(NOTE_VOID);
-        delay(140);
-        play_note(NOTE_E6);
-        delay(280);
-        play_note(NOTE_D6);
-        delay(140);
-        play_note(NOTE_VOID);
-        delay(140);
-        play_note(NOTE_D6);
-        delay(280);
+    /// Play the next note if it is due.
+    ///
+    /// Returns whether the tune is still playing.
+    pub fn update(&mut self) -> bool {
+        if !self.playing {
+            return false;
+        }
+        let now = timer::millis();
+        if (now.wrapping_sub(self.next_at) as i32) < 0 {
+            return true;
+        }
+        match TUNE.get(self.next) {
+            Some(&(countdown, millis)) => {
+                unsafe {
+                    play_note(countdown);
+                }
+                self.next_at = self.next_at.wrapping_add(millis);
+                self.next += 1;
+            }
+            None => self.stop(),
+        }
+        self.playing
+    }
 
-        // turn off
-        pc_speaker_off();
-        delay(140);
+    /// Stop playing right away.
+    pub fn stop(&mut self) {
+        if self.playing {
+            unsafe {
+                pc_speaker_off();
+            }
+            self.playing = false;
+        }
     }
 }
 
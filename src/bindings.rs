@@ -0,0 +1,489 @@
+//! Key bindings of the actions available while playing,
+//! kept in the `[keys]` section of the configuration file:
+//!
+//! ```none
+//! [keys]
+//! layout = qwerty
+//! up = Up, W
+//! quit = Esc
+//! ```
+//!
+//! Keys are identified by scan code,
+//! which depends on the position of the key rather than its label.
+//! The keyboard layout only decides how keys are named.
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ptr::addr_of;
+
+use crate::config;
+use crate::storage;
+use crate::tiles::Move;
+
+/// Something the player can do while playing
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Undo,
+    Hint,
+    Peek,
+    Pause,
+    Quit,
+}
+
+/// All actions, in the order shown in the rebinding screen
+pub const ACTIONS: [Action; 9] = [
+    Action::Up,
+    Action::Down,
+    Action::Left,
+    Action::Right,
+    Action::Undo,
+    Action::Hint,
+    Action::Peek,
+    Action::Pause,
+    Action::Quit,
+];
+
+impl Action {
+    /// The name of the action,
+    /// as shown in the rebinding screen and written in the configuration
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Up => "Up",
+            Action::Down => "Down",
+            Action::Left => "Left",
+            Action::Right => "Right",
+            Action::Undo => "Undo",
+            Action::Hint => "Hint",
+            Action::Peek => "Peek",
+            Action::Pause => "Pause",
+            Action::Quit => "Quit",
+        }
+    }
+
+    /// Whether the action must always keep a key,
+    /// so that a level can be left at any time
+    pub fn is_required(self) -> bool {
+        self == Action::Quit
+    }
+
+    /// The action of moving in a direction
+    pub fn for_direction(m: Move) -> Self {
+        match m {
+            Move::Up => Action::Up,
+            Move::Down => Action::Down,
+            Move::Left => Action::Left,
+            Move::Right => Action::Right,
+        }
+    }
+
+    /// The move of the tiles for a direction action
+    pub fn direction(self) -> Option<Move> {
+        match self {
+            Action::Up => Some(Move::Up),
+            Action::Down => Some(Move::Down),
+            Action::Left => Some(Move::Left),
+            Action::Right => Some(Move::Right),
+            _ => None,
+        }
+    }
+}
+
+/// How the keys are labelled on the keyboard
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum KeyboardLayout {
+    Qwerty,
+    Azerty,
+}
+
+impl KeyboardLayout {
+    /// The name of the layout, as shown in the rebinding screen
+    pub fn name(self) -> &'static str {
+        match self {
+            KeyboardLayout::Qwerty => "QWERTY",
+            KeyboardLayout::Azerty => "AZERTY",
+        }
+    }
+
+    /// The layout after this one in the rebinding screen
+    pub fn next(self) -> Self {
+        match self {
+            KeyboardLayout::Qwerty => KeyboardLayout::Azerty,
+            KeyboardLayout::Azerty => KeyboardLayout::Qwerty,
+        }
+    }
+}
+
+/// Names of the keys of a US (QWERTY) keyboard, by scan code
+/// (the comma is spelled out, as it separates keys in the configuration)
+const KEY_NAMES: [&str; 0x54] = [
+    "",
+    "Esc",
+    "1",
+    "2",
+    "3",
+    "4",
+    "5",
+    "6",
+    "7",
+    "8",
+    "9",
+    "0",
+    "-",
+    "=",
+    "Backspace",
+    "Tab",
+    "Q",
+    "W",
+    "E",
+    "R",
+    "T",
+    "Y",
+    "U",
+    "I",
+    "O",
+    "P",
+    "[",
+    "]",
+    "Enter",
+    "Ctrl",
+    "A",
+    "S",
+    "D",
+    "F",
+    "G",
+    "H",
+    "J",
+    "K",
+    "L",
+    ";",
+    "'",
+    "`",
+    "LShift",
+    "\\",
+    "Z",
+    "X",
+    "C",
+    "V",
+    "B",
+    "N",
+    "M",
+    "Comma",
+    ".",
+    "/",
+    "RShift",
+    "Keypad*",
+    "Alt",
+    "Space",
+    "CapsLock",
+    "F1",
+    "F2",
+    "F3",
+    "F4",
+    "F5",
+    "F6",
+    "F7",
+    "F8",
+    "F9",
+    "F10",
+    "NumLock",
+    "ScrollLock",
+    "Home",
+    "Up",
+    "PgUp",
+    "Keypad-",
+    "Left",
+    "Keypad5",
+    "Right",
+    "Keypad+",
+    "End",
+    "Down",
+    "PgDn",
+    "Insert",
+    "Delete",
+];
+
+/// Names of the keys which differ on a French (AZERTY) keyboard
+const AZERTY_NAMES: [(u8, &str); 9] = [
+    (0x10, "A"),
+    (0x11, "Z"),
+    (0x1e, "Q"),
+    (0x27, "M"),
+    (0x2c, "W"),
+    (0x32, "Comma"),
+    (0x33, ";"),
+    (0x34, ":"),
+    (0x35, "!"),
+];
+
+/// Keys which cannot be bound,
+/// as they already do something else while playing
+/// (Tab toggles the HUD, Enter slides tiles in cursor mode)
+pub const RESERVED_KEYS: [u8; 2] = [0x0f, 0x1c];
+
+/// Get the name of a key in the given layout,
+/// or `None` if the key is not known.
+pub fn key_name(scan_code: u8, layout: KeyboardLayout) -> Option<&'static str> {
+    if layout == KeyboardLayout::Azerty {
+        if let Some((_, name)) = AZERTY_NAMES.iter().find(|(code, _)| *code == scan_code) {
+            return Some(name);
+        }
+    }
+    KEY_NAMES
+        .get(scan_code as usize)
+        .copied()
+        .filter(|name| !name.is_empty())
+}
+
+/// Get the scan code of a key from its name in the given layout
+/// (ignoring case).
+pub fn scan_code(name: &str, layout: KeyboardLayout) -> Option<u8> {
+    (1..KEY_NAMES.len() as u8)
+        .find(|&code| key_name(code, layout).is_some_and(|n| n.eq_ignore_ascii_case(name)))
+}
+
+/// The keys bound to each action
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Bindings {
+    /// how keys are named in the configuration and on screen
+    pub layout: KeyboardLayout,
+    /// primary and secondary scan code of each action, 0 for none
+    keys: [[u8; 2]; ACTIONS.len()],
+}
+
+impl Bindings {
+    /// The bindings used unless configured otherwise
+    pub const DEFAULT: Bindings = Bindings {
+        layout: KeyboardLayout::Qwerty,
+        keys: [
+            // Up and W
+            [0x48, 0x11],
+            // Down and S
+            [0x50, 0x1f],
+            // Left and A
+            [0x4b, 0x1e],
+            // Right and D
+            [0x4d, 0x20],
+            // U and Backspace
+            [0x16, 0x0e],
+            // H
+            [0x23, 0],
+            // Space
+            [0x39, 0],
+            // P
+            [0x19, 0],
+            // Esc
+            [0x01, 0],
+        ],
+    };
+
+    /// Load the bindings from the configuration file,
+    /// keeping the defaults for anything missing or invalid.
+    pub fn load() -> Self {
+        let mut bindings = Bindings::DEFAULT;
+        let Some(text) = config::read() else {
+            return bindings;
+        };
+        let entries = || config::entries(&text).filter(|e| e.section.eq_ignore_ascii_case("keys"));
+
+        // the layout is needed to make sense of the key names
+        for entry in entries() {
+            if entry.key.eq_ignore_ascii_case("layout") {
+                if entry.value.eq_ignore_ascii_case("azerty") {
+                    bindings.layout = KeyboardLayout::Azerty;
+                } else if entry.value.eq_ignore_ascii_case("qwerty") {
+                    bindings.layout = KeyboardLayout::Qwerty;
+                }
+            }
+        }
+        for entry in entries() {
+            let Some(i) = ACTIONS
+                .iter()
+                .position(|a| a.name().eq_ignore_ascii_case(entry.key))
+            else {
+                continue;
+            };
+            let codes: Option<Vec<u8>> = entry
+                .value
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| scan_code(name, bindings.layout))
+                .map(|code| code.filter(|c| !RESERVED_KEYS.contains(c)))
+                .collect();
+            match codes.as_deref() {
+                Some([]) => bindings.keys[i] = [0, 0],
+                Some([primary]) => bindings.keys[i] = [*primary, 0],
+                Some([primary, secondary]) => bindings.keys[i] = [*primary, *secondary],
+                // too many keys or an unknown name
+                _ => {}
+            }
+        }
+        // give back the default key to an action left without one
+        for action in ACTIONS {
+            if action.is_required() && !bindings.has_key(action) {
+                bindings.bind(action, 0, Bindings::DEFAULT.keys(action)[0]);
+            }
+        }
+        bindings
+    }
+
+    /// Write the bindings to the configuration file,
+    /// keeping everything else in it.
+    pub fn save(&self) -> Result<(), storage::Error> {
+        let mut lines = Vec::with_capacity(ACTIONS.len() + 1);
+        lines.push(format!(
+            "layout = {}",
+            self.layout.name().to_ascii_lowercase()
+        ));
+        for action in ACTIONS {
+            lines.push(format!(
+                "{} = {}",
+                action.name().to_ascii_lowercase(),
+                self.describe(action)
+            ));
+        }
+        config::update(|text| config::replace_section(text, "keys", &lines))
+    }
+
+    /// The keys bound to an action (0 for none)
+    pub fn keys(&self, action: Action) -> [u8; 2] {
+        self.keys[action as usize]
+    }
+
+    /// The names of the keys bound to an action, separated by commas
+    pub fn describe(&self, action: Action) -> String {
+        let names: Vec<&str> = self
+            .keys(action)
+            .iter()
+            .filter_map(|&code| key_name(code, self.layout))
+            .collect();
+        names.join(", ")
+    }
+
+    /// Bind a key to an action, in the primary (0) or secondary (1) slot.
+    ///
+    /// The key is taken away from any other action it was bound to,
+    /// unless that would leave a required action without a key.
+    /// Returns whether the key was bound.
+    pub fn bind(&mut self, action: Action, slot: usize, scan_code: u8) -> bool {
+        let previous = *self;
+        for keys in self.keys.iter_mut() {
+            for key in keys.iter_mut() {
+                if *key == scan_code {
+                    *key = 0;
+                }
+            }
+        }
+        self.keys[action as usize][slot] = scan_code;
+        self.tidy();
+        self.keep_required(previous)
+    }
+
+    /// Remove the key in the primary (0) or secondary (1) slot of an action,
+    /// unless it is the last key of a required action.
+    /// Returns whether the key was removed.
+    pub fn unbind(&mut self, action: Action, slot: usize) -> bool {
+        let previous = *self;
+        self.keys[action as usize][slot] = 0;
+        self.tidy();
+        self.keep_required(previous)
+    }
+
+    /// Go back to the previous bindings
+    /// if a required action was left without a key.
+    fn keep_required(&mut self, previous: Bindings) -> bool {
+        if ACTIONS
+            .into_iter()
+            .all(|a| !a.is_required() || self.has_key(a))
+        {
+            return true;
+        }
+        *self = previous;
+        false
+    }
+
+    /// Whether a key leads to the action
+    fn has_key(&self, action: Action) -> bool {
+        self.keys(action)
+            .iter()
+            .any(|&key| self.action(key) == Some(action))
+    }
+
+    /// Move secondary keys up where the primary slot is empty.
+    fn tidy(&mut self) {
+        for keys in self.keys.iter_mut() {
+            if keys[0] == 0 {
+                *keys = [keys[1], 0];
+            }
+        }
+    }
+
+    /// The action bound to a key, if any
+    pub fn action(&self, scan_code: u8) -> Option<Action> {
+        if scan_code == 0 {
+            return None;
+        }
+        ACTIONS
+            .into_iter()
+            .find(|&a| self.keys(a).contains(&scan_code))
+    }
+}
+
+/// The bindings in use
+static mut CURRENT: Bindings = Bindings::DEFAULT;
+
+/// Get the bindings in use.
+pub fn current() -> Bindings {
+    unsafe { *addr_of!(CURRENT) }
+}
+
+/// Change the bindings in use.
+pub fn set_current(bindings: Bindings) {
+    unsafe {
+        CURRENT = bindings;
+    }
+}
+
+/// The action bound to a key in the bindings in use, if any
+pub fn action_for(scan_code: u8) -> Option<Action> {
+    current().action(scan_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_last_key_of_quit_stays() {
+        let mut bindings = Bindings::DEFAULT;
+        assert!(!bindings.unbind(Action::Quit, 0));
+        assert_eq!(bindings, Bindings::DEFAULT);
+        // nor can it be taken by another action
+        assert!(!bindings.bind(Action::Pause, 1, 0x01));
+        assert_eq!(bindings, Bindings::DEFAULT);
+    }
+
+    #[test]
+    fn quit_can_change_keys() {
+        let mut bindings = Bindings::DEFAULT;
+        // Q
+        assert!(bindings.bind(Action::Quit, 1, 0x10));
+        assert!(bindings.unbind(Action::Quit, 0));
+        assert_eq!(bindings.keys(Action::Quit), [0x10, 0]);
+        assert!(bindings.bind(Action::Pause, 1, 0x01));
+        assert_eq!(bindings.action(0x01), Some(Action::Pause));
+    }
+
+    #[test]
+    fn other_actions_can_lose_all_keys() {
+        let mut bindings = Bindings::DEFAULT;
+        assert!(bindings.unbind(Action::Peek, 0));
+        assert_eq!(bindings.keys(Action::Peek), [0, 0]);
+    }
+}
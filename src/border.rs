@@ -0,0 +1,75 @@
+//! Separation drawn around each tile,
+//! so that busy pictures still show where one tile ends
+use crate::screen;
+
+/// Reserved color of the grid lines and the dark side of the bevel
+pub const DARK_COLOR: u8 = 0xFA;
+/// Reserved color of the light side of the bevel
+pub const LIGHT_COLOR: u8 = 0xFB;
+
+/// What is drawn around each tile
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Border {
+    /// tiles touch each other
+    None,
+    /// a thin dark line around each tile
+    Lines,
+    /// a 3D bevel: lighter top and left, darker bottom and right
+    Bevel,
+}
+
+impl Border {
+    /// The name of the border, as shown in the options
+    pub fn name(self) -> &'static str {
+        match self {
+            Border::None => "None",
+            Border::Lines => "Grid lines",
+            Border::Bevel => "Bevel",
+        }
+    }
+
+    /// The border after this one in the options
+    pub fn next(self) -> Self {
+        match self {
+            Border::None => Border::Lines,
+            Border::Lines => Border::Bevel,
+            Border::Bevel => Border::None,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Border::None),
+            1 => Some(Border::Lines),
+            2 => Some(Border::Bevel),
+            _ => None,
+        }
+    }
+
+    /// Width in pixels of the gutter left on each side of a tile
+    pub fn gutter(self) -> u32 {
+        match self {
+            Border::None => 0,
+            Border::Lines => 1,
+            Border::Bevel => 2,
+        }
+    }
+
+    /// Draw the border in the gutter of the tile
+    /// with its top left corner at the given position.
+    pub fn draw(self, x: i32, y: i32, width: u32, height: u32) {
+        let (top_left, bottom_right) = match self {
+            Border::None => return,
+            Border::Lines => (DARK_COLOR, DARK_COLOR),
+            Border::Bevel => (LIGHT_COLOR, DARK_COLOR),
+        };
+        let gutter = self.gutter();
+        let right = x + (width - gutter) as i32;
+        let bottom = y + (height - gutter) as i32;
+        screen::draw_rect(x, y, width, gutter, top_left);
+        screen::draw_rect(x, y, gutter, height, top_left);
+        screen::draw_rect(x, bottom, width, gutter, bottom_right);
+        screen::draw_rect(right, y, gutter, height, bottom_right);
+    }
+}
@@ -0,0 +1,351 @@
+//! The configuration file `TILERS.CFG`,
+//! a plain text file in INI style which players can edit by hand:
+//!
+//! ```none
+//! ; comments start with a semicolon
+//! [section]
+//! key = value
+//! ```
+//!
+//! Section and key names are case insensitive.
+//!
+//! The file is taken byte for byte,
+//! each byte standing for the character of the same code,
+//! so that text in the code page of DOS (accents in comments, say)
+//! is written back untouched.
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::CStr;
+
+use crate::storage;
+
+/// The name of the configuration file
+const FILENAME: &CStr = c"TILERS.CFG";
+
+/// A `key = value` line of the configuration file
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Entry<'a> {
+    /// the section the line is in (empty before the first section)
+    pub section: &'a str,
+    pub key: &'a str,
+    pub value: &'a str,
+}
+
+/// Read the whole configuration file,
+/// or `None` if it is missing or unreadable.
+pub fn read() -> Option<String> {
+    let mut file = dos_x::fs::File::open(FILENAME).ok()?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).ok()?;
+    Some(decode(&data))
+}
+
+/// Change the configuration file,
+/// starting from an empty one if it is missing.
+///
+/// Nothing is written if the file is there but cannot be read,
+/// so that it is never replaced with only the part being changed.
+pub fn update(change: impl FnOnce(&str) -> String) -> Result<(), storage::Error> {
+    let text = match dos_x::fs::File::open(FILENAME) {
+        Ok(mut file) => {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)
+                .map_err(|_| storage::Error::Read)?;
+            decode(&data)
+        }
+        Err(_) => String::new(),
+    };
+    storage::write_file(FILENAME, &encode(&change(&text)))
+}
+
+/// Turn the bytes of the file into text, one character per byte.
+fn decode(data: &[u8]) -> String {
+    data.iter().map(|&b| b as char).collect()
+}
+
+/// Turn text back into the bytes of the file, one byte per character
+/// (`?` for anything which does not fit in a byte).
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| u8::try_from(c).unwrap_or(b'?'))
+        .collect()
+}
+
+/// Go through the `key = value` lines of a configuration,
+/// skipping comments, blank lines and anything malformed.
+pub fn entries(text: &str) -> impl Iterator<Item = Entry<'_>> {
+    let mut section = "";
+    text.lines().filter_map(move |line| {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            return None;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim();
+            return None;
+        }
+        let (key, value) = line.split_once('=')?;
+        Some(Entry {
+            section,
+            key: key.trim(),
+            value: value.trim(),
+        })
+    })
+}
+
+/// Replace the contents of a section of a configuration
+/// with the given lines, keeping everything else as is.
+///
+/// The section is added at the end if it is not there yet.
+pub fn replace_section(text: &str, section: &str, lines: &[String]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut replaced = false;
+    let mut skipping = false;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            skipping = name.trim().eq_ignore_ascii_case(section);
+            if skipping {
+                if replaced {
+                    // drop any repeated section
+                    continue;
+                }
+                push_section(&mut out, section, lines);
+                replaced = true;
+                continue;
+            }
+        }
+        if !skipping {
+            out.push_str(line);
+            out.push_str("\r\n");
+        }
+    }
+    if !replaced {
+        if !out.is_empty() {
+            out.push_str("\r\n");
+        }
+        push_section(&mut out, section, lines);
+    }
+    out
+}
+
+/// Set the value of a key in a section of a configuration,
+/// keeping everything else as is.
+///
+/// The key is added at the end of the section if it is not there yet,
+/// and the section at the end of the configuration.
+pub fn set_value(text: &str, section: &str, key: &str, value: &str) -> String {
+    let line = format!("{} = {}", key, value);
+    let mut out = String::with_capacity(text.len() + line.len() + 2);
+    let mut in_section = false;
+    let mut found_section = false;
+    let mut done = false;
+    for l in text.lines() {
+        let trimmed = l.trim();
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if in_section && !done {
+                push_line(&mut out, &line);
+                done = true;
+            }
+            in_section = name.trim().eq_ignore_ascii_case(section);
+            found_section |= in_section;
+        } else if in_section
+            && !trimmed.starts_with(';')
+            && trimmed
+                .split_once('=')
+                .is_some_and(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+        {
+            if !done {
+                push_line(&mut out, &line);
+                done = true;
+            }
+            // drop any repeated key
+            continue;
+        }
+        push_line(&mut out, l);
+    }
+    if !done {
+        if !found_section {
+            if !out.is_empty() {
+                out.push_str("\r\n");
+            }
+            push_line(&mut out, &format!("[{}]", section));
+        }
+        push_line(&mut out, &line);
+    }
+    out
+}
+
+/// Append a line to a configuration.
+fn push_line(out: &mut String, line: &str) {
+    out.push_str(line);
+    out.push_str("\r\n");
+}
+
+/// Append a section with its lines to a configuration.
+fn push_section(out: &mut String, section: &str, lines: &[String]) {
+    out.push('[');
+    out.push_str(section);
+    out.push_str("]\r\n");
+    for line in lines {
+        out.push_str(line);
+        out.push_str("\r\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn bytes_outside_ascii_survive_a_round_trip() {
+        // "; Réglages" in code page 437, then a key
+        let data = b"; R\x82glages\r\n[options]\r\nsound = off\r\n";
+        let text = decode(data);
+        assert_eq!(encode(&set_value(&text, "options", "hud", "on")), {
+            let mut expected = data.to_vec();
+            expected.extend_from_slice(b"hud = on\r\n");
+            expected
+        });
+    }
+
+    #[test]
+    fn entries_skip_comments_and_blank_lines() {
+        let text = "; a comment\r\n\r\n  ; indented comment\r\nkey = value\r\n";
+        let entries: Vec<Entry> = entries(text).collect();
+        assert_eq!(
+            entries,
+            vec![Entry {
+                section: "",
+                key: "key",
+                value: "value",
+            }]
+        );
+    }
+
+    #[test]
+    fn entries_keep_track_of_sections() {
+        let text = "top = 0\n[first]\na = 1\n[ second ]\nb = 2\nc=3\n";
+        let entries: Vec<(&str, &str, &str)> =
+            entries(text).map(|e| (e.section, e.key, e.value)).collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("", "top", "0"),
+                ("first", "a", "1"),
+                ("second", "b", "2"),
+                ("second", "c", "3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn entries_keep_the_case_of_names() {
+        let text = "[Keys]\nLayout = AZERTY\n";
+        let entry = entries(text).next().unwrap();
+        assert!(entry.section.eq_ignore_ascii_case("keys"));
+        assert_eq!(entry.key, "Layout");
+        assert_eq!(entry.value, "AZERTY");
+    }
+
+    #[test]
+    fn entries_skip_malformed_lines() {
+        let text = "[options]\nno equals sign\n[unterminated\nsound = off\n= nameless\n";
+        let entries: Vec<(&str, &str, &str)> =
+            entries(text).map(|e| (e.section, e.key, e.value)).collect();
+        // the unterminated section header is not a section either
+        assert_eq!(
+            entries,
+            vec![("options", "sound", "off"), ("options", "", "nameless")]
+        );
+    }
+
+    #[test]
+    fn entries_split_on_the_first_equals_sign() {
+        let entry = entries("path = C:\\A=B").next().unwrap();
+        assert_eq!(entry.key, "path");
+        assert_eq!(entry.value, "C:\\A=B");
+    }
+
+    #[test]
+    fn replace_section_keeps_other_sections() {
+        let text =
+            "; settings\r\n[keys]\r\nup = W\r\n[options]\r\nsound = on\r\n[game]\r\nseed = 5\r\n";
+        let lines = [String::from("sound = off"), String::from("hud = on")];
+        assert_eq!(
+            replace_section(text, "options", &lines),
+            "; settings\r\n[keys]\r\nup = W\r\n[options]\r\nsound = off\r\nhud = on\r\n[game]\r\nseed = 5\r\n"
+        );
+    }
+
+    #[test]
+    fn replace_section_ignores_case() {
+        let text = "[OPTIONS]\nsound = on\n";
+        let lines = [String::from("sound = off")];
+        assert_eq!(
+            replace_section(text, "options", &lines),
+            "[options]\r\nsound = off\r\n"
+        );
+    }
+
+    #[test]
+    fn replace_section_drops_duplicates() {
+        let text = "[options]\nsound = on\n[keys]\nup = W\n[options]\nhud = on\n";
+        let lines = [String::from("sound = off")];
+        assert_eq!(
+            replace_section(text, "options", &lines),
+            "[options]\r\nsound = off\r\n[keys]\r\nup = W\r\n"
+        );
+    }
+
+    #[test]
+    fn set_value_replaces_only_the_key() {
+        let text = "[options]\r\n; shown at the bottom\r\nHUD = off\r\nsound = off\r\n[game]\r\nhud = 1\r\n";
+        assert_eq!(
+            set_value(text, "options", "hud", "on"),
+            "[options]\r\n; shown at the bottom\r\nhud = on\r\nsound = off\r\n[game]\r\nhud = 1\r\n"
+        );
+    }
+
+    #[test]
+    fn set_value_drops_repeated_keys() {
+        let text = "[options]\nhud = off\nhud = off\n";
+        assert_eq!(
+            set_value(text, "options", "hud", "on"),
+            "[options]\r\nhud = on\r\n"
+        );
+    }
+
+    #[test]
+    fn set_value_adds_missing_key_to_the_section() {
+        let text = "[options]\nsound = off\n[game]\nseed = 5\n";
+        assert_eq!(
+            set_value(text, "options", "hud", "on"),
+            "[options]\r\nsound = off\r\nhud = on\r\n[game]\r\nseed = 5\r\n"
+        );
+        assert_eq!(
+            set_value("[options]\nsound = off\n", "options", "hud", "on"),
+            "[options]\r\nsound = off\r\nhud = on\r\n"
+        );
+    }
+
+    #[test]
+    fn set_value_adds_missing_section() {
+        assert_eq!(
+            set_value("[game]\nseed = 5\n", "options", "hud", "on"),
+            "[game]\r\nseed = 5\r\n\r\n[options]\r\nhud = on\r\n"
+        );
+    }
+
+    #[test]
+    fn replace_section_appends_missing_section() {
+        let lines = [String::from("up = W")];
+        assert_eq!(
+            replace_section("[game]\nseed = 5\n", "keys", &lines),
+            "[game]\r\nseed = 5\r\n\r\n[keys]\r\nup = W\r\n"
+        );
+        assert_eq!(replace_section("", "keys", &lines), "[keys]\r\nup = W\r\n");
+    }
+}
@@ -0,0 +1,136 @@
+//! Bitmap font rendering in video mode 13h.
+//!
+//! The glyphs are the 8x8 characters of the video BIOS ROM font,
+//! which are copied from conventional memory on initialization.
+use core::ffi::c_void;
+use dos_x::djgpp::dpmi::{__dpmi_int, __dpmi_regs};
+
+use crate::screen;
+
+extern "C" {
+    fn dosmemget(offset: u32, length: usize, buffer: *mut c_void);
+}
+
+/// Width and height of each glyph in pixels
+pub const GLYPH_SIZE: u32 = 8;
+
+/// Number of glyphs in the font (only the lower half of CP437)
+const GLYPH_COUNT: usize = 128;
+
+/// Color of the drop shadow (black)
+const SHADOW_COLOR: u8 = 0xFF;
+
+/// The glyph bitmaps, one byte per row,
+/// most significant bit on the left
+static mut FONT: [u8; GLYPH_COUNT * 8] = [0; GLYPH_COUNT * 8];
+
+/// Load the font from the video BIOS.
+///
+/// Must be called once before drawing any text.
+pub fn init() {
+    unsafe {
+        // get font information: 8x8 ROM font (characters 00h-7Fh)
+        let mut regs: __dpmi_regs = core::mem::zeroed();
+        regs.x.ax = 0x1130;
+        regs.h.bh = 0x03;
+        __dpmi_int(0x10, &mut regs);
+
+        let address = ((regs.x.es as u32) << 4) + regs.x.bp as u32;
+        dosmemget(
+            address,
+            GLYPH_COUNT * 8,
+            core::ptr::addr_of_mut!(FONT) as *mut c_void,
+        );
+    }
+}
+
+/// Get the bitmap of a character,
+/// using '?' for anything outside of ASCII.
+fn glyph(c: u8) -> [u8; 8] {
+    let c = if (c as usize) < GLYPH_COUNT { c } else { b'?' } as usize;
+    let font = unsafe { &*core::ptr::addr_of!(FONT) };
+    let mut glyph = [0; 8];
+    glyph.copy_from_slice(&font[c * 8..c * 8 + 8]);
+    glyph
+}
+
+/// Width of a string in pixels
+pub fn text_width(text: &str) -> u32 {
+    text.len() as u32 * GLYPH_SIZE
+}
+
+/// Draw a single character with its top left corner at the given position,
+/// each pixel of the glyph being a square of `scale` pixels,
+/// filling each run of pixels with `fill(x, y, width, height)`.
+fn draw_glyph(x: i32, y: i32, c: u8, scale: u32, fill: &mut impl FnMut(i32, i32, u32, u32)) {
+    let step = scale as i32;
+    for (row, bits) in glyph(c).into_iter().enumerate() {
+        let py = y + row as i32 * step;
+        // draw each run of set bits in one go
+        let mut col = 0;
+        while col < 8 {
+            if bits & (0x80 >> col) == 0 {
+                col += 1;
+                continue;
+            }
+            let start = col;
+            while col < 8 && bits & (0x80 >> col) != 0 {
+                col += 1;
+            }
+            fill(x + start * step, py, (col - start) as u32 * scale, scale);
+        }
+    }
+}
+
+/// Draw a string with its top left corner at the given position,
+/// without a shadow.
+pub fn draw_text_plain(x: i32, y: i32, text: &str, color: u8) {
+    draw_text_scaled(x, y, text, color, 1);
+}
+
+/// Draw a string with its top left corner at the given position,
+/// enlarged by an integer factor and without a shadow.
+pub fn draw_text_scaled(x: i32, y: i32, text: &str, color: u8, scale: u32) {
+    let mut fill = |x, y, width, height| screen::draw_rect(x, y, width, height, color);
+    draw_glyphs(x, y, text, scale, &mut fill);
+}
+
+/// Draw a string with its top left corner at the given position
+/// into a full screen image (320x200) other than the screen itself,
+/// enlarged by an integer factor and without a shadow.
+pub fn draw_text_scaled_into(target: &mut [u8], x: i32, y: i32, text: &str, color: u8, scale: u32) {
+    let mut fill = |x, y, width, height| screen::draw_rect_into(target, x, y, width, height, color);
+    draw_glyphs(x, y, text, scale, &mut fill);
+}
+
+/// Draw each character of a string side by side.
+fn draw_glyphs(x: i32, y: i32, text: &str, scale: u32, fill: &mut impl FnMut(i32, i32, u32, u32)) {
+    for (i, c) in text.bytes().enumerate() {
+        let advance = (GLYPH_SIZE * scale) as i32;
+        draw_glyph(x + i as i32 * advance, y, c, scale, fill);
+    }
+}
+
+/// Draw a string with its top left corner at the given position,
+/// with a drop shadow so that it is legible over a picture.
+pub fn draw_text(x: i32, y: i32, text: &str, color: u8) {
+    draw_text_plain(x + 1, y + 1, text, SHADOW_COLOR);
+    draw_text_plain(x, y, text, color);
+}
+
+/// Draw a string horizontally centered on the screen,
+/// with a drop shadow.
+pub fn draw_text_centered(y: i32, text: &str, color: u8) {
+    let x = (320 - text_width(text) as i32) / 2;
+    draw_text(x, y, text, color);
+}
+
+/// Draw a string horizontally centered on the screen
+/// over a black box,
+/// for messages which must stand out over anything.
+pub fn draw_banner(y: i32, text: &str, color: u8) {
+    let width = text_width(text);
+    let x = (320 - width as i32) / 2;
+    screen::draw_rect(x - 4, y - 2, width + 8, GLYPH_SIZE + 4, SHADOW_COLOR);
+    draw_text_plain(x, y, text, color);
+}
@@ -0,0 +1,112 @@
+//! Persistent table of the best scores for each level picture and grid size
+use alloc::vec::Vec;
+use core::ffi::CStr;
+
+use crate::storage::{self, Reader};
+
+/// The file which holds the high score table
+const FILENAME: &CStr = c"TILERS.HI";
+
+/// Magic bytes of the high score file
+const MAGIC: &[u8; 4] = b"TLHI";
+
+/// Version of the high score file format
+const VERSION: u8 = 2;
+
+/// Identifier of a level picture:
+/// the MD5 digest of the PNG file contents,
+/// so that custom pictures get their own entries.
+pub type PictureId = [u8; 16];
+
+/// The size of a puzzle, in columns and rows
+pub type GridSize = (u8, u8);
+
+/// The best scores for a level picture
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct HighScore {
+    /// the smallest number of moves to solve the puzzle
+    pub moves: u32,
+    /// the shortest time to solve the puzzle, in BIOS ticks
+    pub ticks: u32,
+}
+
+/// The table of high scores
+#[derive(Debug, Default)]
+pub struct HighScores {
+    /// the scores of each picture on each grid size,
+    /// as moves on different grid sizes cannot be compared
+    entries: Vec<(PictureId, GridSize, HighScore)>,
+}
+
+impl HighScores {
+    /// Load the high score table from disk.
+    ///
+    /// A missing or corrupt file results in an empty table.
+    pub fn load() -> Self {
+        storage::read_record(FILENAME, MAGIC, VERSION)
+            .and_then(|payload| Self::decode(&payload))
+            .unwrap_or_default()
+    }
+
+    fn decode(payload: &[u8]) -> Option<Self> {
+        let mut reader = Reader::new(payload);
+        let count = reader.u16()?;
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let mut id = PictureId::default();
+            id.copy_from_slice(reader.bytes(16)?);
+            let grid = (reader.u8()?, reader.u8()?);
+            let moves = reader.u32()?;
+            let ticks = reader.u32()?;
+            entries.push((id, grid, HighScore { moves, ticks }));
+        }
+        if !reader.is_empty() {
+            return None;
+        }
+        Some(HighScores { entries })
+    }
+
+    /// Save the high score table to disk.
+    pub fn save(&self) -> Result<(), storage::Error> {
+        let mut payload = Vec::with_capacity(2 + self.entries.len() * 26);
+        payload.extend_from_slice(&(self.entries.len() as u16).to_le_bytes());
+        for (id, (cols, rows), score) in &self.entries {
+            payload.extend_from_slice(id);
+            payload.extend_from_slice(&[*cols, *rows]);
+            payload.extend_from_slice(&score.moves.to_le_bytes());
+            payload.extend_from_slice(&score.ticks.to_le_bytes());
+        }
+        storage::write_record(FILENAME, MAGIC, VERSION, &payload)
+    }
+
+    /// Get the best scores for a level picture on a grid size, if any.
+    pub fn get(&self, id: &PictureId, grid: GridSize) -> Option<HighScore> {
+        self.entries
+            .iter()
+            .find(|(entry_id, entry_grid, _)| entry_id == id && *entry_grid == grid)
+            .map(|(_, _, score)| *score)
+    }
+
+    /// Submit the score of a solved puzzle.
+    ///
+    /// The best moves and the best time are kept independently.
+    /// Returns whether any of them was improved.
+    pub fn submit(&mut self, id: &PictureId, grid: GridSize, moves: u32, ticks: u32) -> bool {
+        match self
+            .entries
+            .iter_mut()
+            .find(|(entry_id, entry_grid, _)| entry_id == id && *entry_grid == grid)
+        {
+            Some((_, _, score)) => {
+                let improved = moves < score.moves || ticks < score.ticks;
+                score.moves = score.moves.min(moves);
+                score.ticks = score.ticks.min(ticks);
+                improved
+            }
+            None => {
+                self.entries.push((*id, grid, HighScore { moves, ticks }));
+                true
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Non-blocking input through our keyboard handler
+//! (or the BIOS keyboard services if it is not installed),
+//! plus the joystick (if enabled)
+use dos_x::djgpp::dpmi::{__dpmi_int, __dpmi_regs};
+use dos_x::djgpp::pc::inportb;
+
+use crate::bindings::{self, Action};
+use crate::joystick::{self, Event};
+use crate::keyboard;
+use crate::tiles::Move;
+
+/// Zero flag in the FLAGS register
+const ZERO_FLAG: u16 = 0x40;
+
+/// How the arrow keys are used while playing
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Controls {
+    /// the arrows move the tile next to the empty slot
+    Direct,
+    /// the arrows move a cursor around the grid
+    /// and Enter slides the tile under it
+    Cursor,
+}
+
+impl Controls {
+    /// The name of the control scheme, as shown in the options
+    pub fn name(self) -> &'static str {
+        match self {
+            Controls::Direct => "Move tiles",
+            Controls::Cursor => "Cursor",
+        }
+    }
+
+    /// The control scheme after this one in the options
+    pub fn next(self) -> Self {
+        match self {
+            Controls::Direct => Controls::Cursor,
+            Controls::Cursor => Controls::Direct,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Controls::Direct),
+            1 => Some(Controls::Cursor),
+            _ => None,
+        }
+    }
+}
+
+/// Scan code of Enter
+const ENTER: u8 = 0x1c;
+
+/// Check whether a key was pressed and take it out of the keyboard buffer.
+///
+/// Returns the scan code of the key,
+/// or `None` if no key is waiting to be read.
+/// To get around the menus, the joystick produces
+/// the scan codes of the arrow keys and of Enter (first button).
+pub fn poll_key() -> Option<u8> {
+    poll_keyboard().or_else(|| joystick::poll().and_then(menu_key))
+}
+
+/// The scan code of the key standing for a joystick event in the menus
+fn menu_key(event: Event) -> Option<u8> {
+    match event {
+        Event::Direction(Move::Left) => Some(0x4b),
+        Event::Direction(Move::Right) => Some(0x4d),
+        Event::Direction(Move::Up) => Some(0x48),
+        Event::Direction(Move::Down) => Some(0x50),
+        Event::Confirm => Some(ENTER),
+        Event::Hint => None,
+    }
+}
+
+/// Check whether a key was pressed or the joystick was used while playing,
+/// along with the action bound to it.
+///
+/// Returns the scan code of the key (0 for actions of the joystick),
+/// or `None` if nothing happened.
+/// The stick moves in the direction pushed
+/// whatever keys the directions are bound to,
+/// the first button stands for Enter (which cannot be rebound)
+/// and the second button asks for a hint.
+pub fn poll_action() -> Option<(u8, Option<Action>)> {
+    if let Some(key) = poll_keyboard() {
+        return Some((key, bindings::action_for(key)));
+    }
+    Some(match joystick::poll()? {
+        Event::Direction(m) => (0, Some(Action::for_direction(m))),
+        Event::Confirm => (ENTER, None),
+        Event::Hint => (0, Some(Action::Hint)),
+    })
+}
+
+fn poll_keyboard() -> Option<u8> {
+    if keyboard::is_installed() {
+        // key releases are only of interest to `is_key_held`
+        while let Some(event) = keyboard::poll_event() {
+            if event.pressed {
+                return Some(event.scan_code);
+            }
+        }
+        return None;
+    }
+    unsafe {
+        let mut regs: __dpmi_regs = core::mem::zeroed();
+        // check for keystroke
+        regs.h.ah = 0x01;
+        __dpmi_int(0x16, &mut regs);
+        if regs.x.flags & ZERO_FLAG != 0 {
+            return None;
+        }
+
+        // read the keystroke proper
+        let mut regs: __dpmi_regs = core::mem::zeroed();
+        regs.h.ah = 0x00;
+        __dpmi_int(0x16, &mut regs);
+        Some(regs.h.ah)
+    }
+}
+
+/// Check whether the key with the given scan code is still held down.
+///
+/// Without our keyboard handler,
+/// the BIOS does not report key releases,
+/// so this looks at the last scan code
+/// received by the keyboard controller
+/// (pressing another key also counts as releasing it).
+pub fn is_key_held(scan_code: u8) -> bool {
+    if keyboard::is_installed() {
+        return keyboard::is_held(scan_code);
+    }
+    unsafe { inportb(0x60) == scan_code }
+}
+
+/// Wait for the player to answer a yes or no question
+/// by pressing Y or N (ESC also counts as no).
+pub fn wait_yes_no() -> bool {
+    loop {
+        match poll_key() {
+            // Y
+            Some(0x15) => return true,
+            // N or ESC
+            Some(0x31) | Some(0x01) => return false,
+            _ => {}
+        }
+    }
+}
+
+/// Discard all keys waiting in the keyboard buffer.
+pub fn flush() {
+    while poll_key().is_some() {}
+}
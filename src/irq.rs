@@ -0,0 +1,90 @@
+//! Hooking hardware interrupts with protected mode handlers
+//! through the DJGPP DPMI wrappers
+use core::arch::asm;
+use core::ffi::c_void;
+use core::mem::size_of;
+
+/// Protected mode interrupt handler information
+/// (`_go32_dpmi_seginfo`)
+#[repr(C)]
+pub struct SegInfo {
+    size: u32,
+    pub pm_offset: u32,
+    pm_selector: u16,
+    rm_offset: u16,
+    rm_segment: u16,
+}
+
+impl SegInfo {
+    pub const fn new() -> Self {
+        SegInfo {
+            size: 0,
+            pm_offset: 0,
+            pm_selector: 0,
+            rm_offset: 0,
+            rm_segment: 0,
+        }
+    }
+}
+
+extern "C" {
+    fn _go32_dpmi_get_protected_mode_interrupt_vector(vector: i32, info: *mut SegInfo) -> i32;
+    fn _go32_dpmi_set_protected_mode_interrupt_vector(vector: i32, info: *mut SegInfo) -> i32;
+    fn _go32_dpmi_allocate_iret_wrapper(info: *mut SegInfo) -> i32;
+    fn _go32_dpmi_free_iret_wrapper(info: *mut SegInfo) -> i32;
+    fn _go32_dpmi_lock_code(address: *mut c_void, size: u32) -> i32;
+    fn _go32_dpmi_lock_data(address: *mut c_void, size: u32) -> i32;
+    /// Register a function to call when the program exits
+    /// (also after a panic, which ends with `exit`)
+    pub fn atexit(function: extern "C" fn()) -> i32;
+}
+
+/// Lock the memory of a variable used by an interrupt handler,
+/// so that it is never paged out.
+pub unsafe fn lock_data<T>(data: *mut T) {
+    _go32_dpmi_lock_data(data as *mut c_void, size_of::<T>() as u32);
+}
+
+/// Lock the code of an interrupt handler,
+/// so that it is never paged out.
+pub unsafe fn lock_code(handler: extern "C" fn()) {
+    _go32_dpmi_lock_code(handler as *mut c_void, 4096);
+}
+
+/// Point an interrupt vector to a handler,
+/// keeping the handler which was there in `old`.
+///
+/// `new` receives the wrapper of the handler,
+/// to be kept until [`unhook`].
+/// Returns `false` if the wrapper could not be allocated,
+/// in which case the vector is left untouched.
+pub unsafe fn hook(
+    vector: i32,
+    handler: extern "C" fn(),
+    old: *mut SegInfo,
+    new: *mut SegInfo,
+) -> bool {
+    _go32_dpmi_get_protected_mode_interrupt_vector(vector, old);
+
+    let cs: u16;
+    asm!("mov {0:x}, cs", out(reg) cs);
+    (*new).pm_offset = handler as usize as u32;
+    (*new).pm_selector = cs;
+    if _go32_dpmi_allocate_iret_wrapper(new) != 0 {
+        return false;
+    }
+
+    asm!("cli");
+    _go32_dpmi_set_protected_mode_interrupt_vector(vector, new);
+    asm!("sti");
+    true
+}
+
+/// Put back the handler saved by [`hook`]
+/// and release the wrapper of ours.
+pub unsafe fn unhook(vector: i32, old: *mut SegInfo, new: *mut SegInfo) {
+    asm!("cli");
+    _go32_dpmi_set_protected_mode_interrupt_vector(vector, old);
+    asm!("sti");
+    _go32_dpmi_free_iret_wrapper(new);
+}
@@ -0,0 +1,196 @@
+//! Joystick input through the standard PC game port.
+//!
+//! The position of each axis is measured by triggering the one-shots
+//! of the game port and counting how long their bits take to clear.
+//! The counts depend on the stick and on the speed of the machine,
+//! hence the calibration.
+use core::ptr::{addr_of, addr_of_mut};
+use dos_x::djgpp::pc::{inportb, outportb};
+
+use crate::tiles::Move;
+use crate::timer;
+
+/// I/O port of the game port
+const GAME_PORT: u16 = 0x201;
+
+/// Bits of the X and Y axes of the first joystick
+const AXIS_BITS: u8 = 0x03;
+
+/// Position of the bits of the two buttons of the first joystick
+/// (which read 0 while held down)
+const BUTTON_SHIFT: u8 = 4;
+
+/// Number of port reads after which the joystick is deemed absent
+const READ_TIMEOUT: u16 = 10_000;
+
+/// Time before a held direction starts repeating in milliseconds
+const REPEAT_DELAY_MILLIS: u32 = 400;
+
+/// Time between repeats of a held direction in milliseconds
+const REPEAT_INTERVAL_MILLIS: u32 = 150;
+
+/// How far (in percent of its range) an axis must be pushed to count
+const DEAD_ZONE: i32 = 50;
+
+/// Bit of the first button, used to confirm
+pub const BUTTON_CONFIRM: u8 = 0x01;
+
+/// Bit of the second button, used to ask for a hint
+pub const BUTTON_HINT: u8 = 0x02;
+
+/// The state of the joystick at one point in time
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Reading {
+    /// the X and Y axis counts
+    pub axes: [u16; 2],
+    /// the buttons held down (`BUTTON_CONFIRM`, `BUTTON_HINT`)
+    pub buttons: u8,
+}
+
+/// The axis counts of the joystick at its extremes and at rest
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Calibration {
+    /// the top left corner
+    pub min: [u16; 2],
+    /// the stick left alone
+    pub center: [u16; 2],
+    /// the bottom right corner
+    pub max: [u16; 2],
+}
+
+impl Calibration {
+    /// Whether the center lies strictly between the extremes on both axes
+    pub fn is_valid(&self) -> bool {
+        (0..2).all(|i| self.min[i] < self.center[i] && self.center[i] < self.max[i])
+    }
+
+    /// The direction the stick is pushed towards, if far enough from the center.
+    ///
+    /// When pushed diagonally, the axis deflected the most wins.
+    fn direction(&self, axes: [u16; 2]) -> Option<Move> {
+        // deflection of each axis relative to its range, from -100 to 100
+        let deflection = |i: usize| -> i32 {
+            let value = axes[i] as i32;
+            let center = self.center[i] as i32;
+            if value < center {
+                (value - center) * 100 / (center - self.min[i] as i32).max(1)
+            } else {
+                (value - center) * 100 / (self.max[i] as i32 - center).max(1)
+            }
+        };
+        let (x, y) = (deflection(0), deflection(1));
+        if x.abs().max(y.abs()) < DEAD_ZONE {
+            None
+        } else if x.abs() >= y.abs() {
+            Some(if x < 0 { Move::Left } else { Move::Right })
+        } else {
+            Some(if y < 0 { Move::Up } else { Move::Down })
+        }
+    }
+}
+
+/// Something the player did with the joystick
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Event {
+    /// the stick was pushed (or is held) in a direction
+    Direction(Move),
+    /// the first button was pressed
+    Confirm,
+    /// the second button was pressed
+    Hint,
+}
+
+/// The calibration in use, or `None` if the joystick is disabled
+static mut CALIBRATION: Option<Calibration> = None;
+
+/// The buttons held down at the last poll
+static mut LAST_BUTTONS: u8 = 0;
+
+/// The direction held at the last poll
+/// and when it is due to repeat
+static mut LAST_DIRECTION: Option<(Move, u32)> = None;
+
+/// Enable the joystick with the given calibration,
+/// or disable it with `None`.
+pub fn set_calibration(calibration: Option<Calibration>) {
+    unsafe {
+        CALIBRATION = calibration;
+        LAST_BUTTONS = 0;
+        LAST_DIRECTION = None;
+    }
+}
+
+/// Read the state of the joystick from the game port.
+///
+/// Returns `None` if no joystick is connected.
+pub fn read() -> Option<Reading> {
+    unsafe {
+        // fire the one-shots
+        outportb(GAME_PORT, 0);
+        let mut axes = [0; 2];
+        let mut bits = inportb(GAME_PORT);
+        let mut count = 0;
+        while bits & AXIS_BITS != 0 {
+            if count == READ_TIMEOUT {
+                return None;
+            }
+            for (i, axis) in axes.iter_mut().enumerate() {
+                if bits & (1 << i) != 0 {
+                    *axis += 1;
+                }
+            }
+            count += 1;
+            bits = inportb(GAME_PORT);
+        }
+        Some(Reading {
+            axes,
+            buttons: (!bits >> BUTTON_SHIFT) & (BUTTON_CONFIRM | BUTTON_HINT),
+        })
+    }
+}
+
+/// Check whether a joystick is connected to the game port.
+pub fn is_present() -> bool {
+    read().is_some()
+}
+
+/// Check the joystick for something new,
+/// repeating a held direction after a delay.
+///
+/// Returns `None` if nothing happened or the joystick is disabled.
+pub fn poll() -> Option<Event> {
+    let calibration = unsafe { (*addr_of!(CALIBRATION))? };
+    let reading = read()?;
+
+    // buttons count when they go down
+    let last_buttons = unsafe { &mut *addr_of_mut!(LAST_BUTTONS) };
+    let pressed = reading.buttons & !*last_buttons;
+    *last_buttons = reading.buttons;
+    if pressed & BUTTON_CONFIRM != 0 {
+        return Some(Event::Confirm);
+    }
+    if pressed & BUTTON_HINT != 0 {
+        return Some(Event::Hint);
+    }
+
+    let last_direction = unsafe { &mut *addr_of_mut!(LAST_DIRECTION) };
+    let now = timer::millis();
+    match (calibration.direction(reading.axes), *last_direction) {
+        (None, _) => {
+            *last_direction = None;
+            None
+        }
+        // still held: repeat once due
+        (Some(m), Some((last, due))) if m == last => {
+            if (now.wrapping_sub(due) as i32) < 0 {
+                return None;
+            }
+            *last_direction = Some((m, now.wrapping_add(REPEAT_INTERVAL_MILLIS)));
+            Some(Event::Direction(m))
+        }
+        (Some(m), _) => {
+            *last_direction = Some((m, now.wrapping_add(REPEAT_DELAY_MILLIS)));
+            Some(Event::Direction(m))
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Keyboard interrupt (IRQ1) handler
+//! recording key presses and releases in a queue
+//! and keeping track of the keys held down.
+//!
+//! The BIOS keyboard handler is bypassed while this one is installed,
+//! so nothing ends up in the BIOS keyboard buffer.
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use dos_x::djgpp::pc::{inportb, outportb};
+
+use crate::irq::{self, SegInfo};
+
+/// Interrupt vector of IRQ1, raised by the keyboard controller
+const KEYBOARD_VECTOR: i32 = 0x09;
+
+/// Data port of the keyboard controller
+const KEYBOARD_PORT: u16 = 0x60;
+
+/// Scan code prefix of the extended keys (arrows, gray keys)
+const EXTENDED_PREFIX: u8 = 0xE0;
+
+/// Scan code prefix of the Pause key, followed by 5 more bytes
+const PAUSE_PREFIX: u8 = 0xE1;
+
+/// Bit set in the scan codes of key releases
+const RELEASE_BIT: u8 = 0x80;
+
+/// Number of events the queue can hold
+/// (must be a power of 2)
+const QUEUE_SIZE: usize = 64;
+
+/// A key going down or up
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct KeyEvent {
+    /// the scan code of the key
+    /// (the same for extended keys and their keypad counterparts)
+    pub scan_code: u8,
+    /// whether the key was pressed (or repeated) rather than released
+    pub pressed: bool,
+}
+
+/// The events received, as raw scan codes, in a ring buffer
+static mut QUEUE: [u8; QUEUE_SIZE] = [0; QUEUE_SIZE];
+/// Index of the next event to read
+static mut HEAD: usize = 0;
+/// Index of the next event to write
+static mut TAIL: usize = 0;
+
+/// Which keys are held down, by scan code
+static mut HELD: [bool; 128] = [false; 128];
+
+/// Whether the last byte received was the extended key prefix
+static mut EXTENDED: bool = false;
+
+/// Number of bytes of the Pause key still to ignore
+static mut SKIP: u8 = 0;
+
+/// Whether our handler is in place
+static mut INSTALLED: bool = false;
+
+/// The interrupt handler which was there before
+static mut OLD_HANDLER: SegInfo = SegInfo::new();
+
+/// Our interrupt handler, wrapped to return with `iret`
+static mut NEW_HANDLER: SegInfo = SegInfo::new();
+
+/// Handle IRQ1, taking in one byte from the keyboard controller.
+extern "C" fn keyboard_handler() {
+    unsafe {
+        let code = inportb(KEYBOARD_PORT);
+        let extended = read_volatile(addr_of!(EXTENDED));
+        write_volatile(addr_of_mut!(EXTENDED), code == EXTENDED_PREFIX);
+
+        let skip = read_volatile(addr_of!(SKIP));
+        if skip > 0 {
+            write_volatile(addr_of_mut!(SKIP), skip - 1);
+        } else if code == PAUSE_PREFIX {
+            write_volatile(addr_of_mut!(SKIP), 5);
+        } else if code != EXTENDED_PREFIX
+            // ignore the fake shifts sent along with some extended keys
+            && !(extended && matches!(code & !RELEASE_BIT, 0x2A | 0x36))
+        {
+            let held = &mut *addr_of_mut!(HELD);
+            held[(code & !RELEASE_BIT) as usize] = code & RELEASE_BIT == 0;
+
+            // drop the event if the queue is full
+            let tail = read_volatile(addr_of!(TAIL));
+            let next = (tail + 1) % QUEUE_SIZE;
+            if next != read_volatile(addr_of!(HEAD)) {
+                (*addr_of_mut!(QUEUE))[tail] = code;
+                write_volatile(addr_of_mut!(TAIL), next);
+            }
+        }
+
+        // end of interrupt
+        outportb(0x20, 0x20);
+    }
+}
+
+/// Install the keyboard handler in place of the BIOS one.
+///
+/// Everything is restored when the program exits.
+/// If the handler cannot be installed,
+/// the BIOS keyboard services keep working as usual.
+pub fn install() {
+    unsafe {
+        if INSTALLED {
+            return;
+        }
+        irq::lock_data(addr_of_mut!(QUEUE));
+        irq::lock_data(addr_of_mut!(HEAD));
+        irq::lock_data(addr_of_mut!(TAIL));
+        irq::lock_data(addr_of_mut!(HELD));
+        irq::lock_data(addr_of_mut!(EXTENDED));
+        irq::lock_data(addr_of_mut!(SKIP));
+        irq::lock_code(keyboard_handler);
+
+        if !irq::hook(
+            KEYBOARD_VECTOR,
+            keyboard_handler,
+            addr_of_mut!(OLD_HANDLER),
+            addr_of_mut!(NEW_HANDLER),
+        ) {
+            return;
+        }
+        INSTALLED = true;
+
+        irq::atexit(uninstall_at_exit);
+    }
+}
+
+extern "C" fn uninstall_at_exit() {
+    uninstall();
+}
+
+/// Put back the original keyboard handler.
+///
+/// Called on exit, and also from the panic handler
+/// so that the keyboard works again right away.
+pub fn uninstall() {
+    unsafe {
+        if !INSTALLED {
+            return;
+        }
+        irq::unhook(
+            KEYBOARD_VECTOR,
+            addr_of_mut!(OLD_HANDLER),
+            addr_of_mut!(NEW_HANDLER),
+        );
+        INSTALLED = false;
+    }
+}
+
+/// Whether the keyboard handler is in place
+pub fn is_installed() -> bool {
+    unsafe { INSTALLED }
+}
+
+/// Take the oldest event out of the queue,
+/// or `None` if the queue is empty.
+pub fn poll_event() -> Option<KeyEvent> {
+    unsafe {
+        let head = read_volatile(addr_of!(HEAD));
+        if head == read_volatile(addr_of!(TAIL)) {
+            return None;
+        }
+        let code = (*addr_of!(QUEUE))[head];
+        write_volatile(addr_of_mut!(HEAD), (head + 1) % QUEUE_SIZE);
+        Some(KeyEvent {
+            scan_code: code & !RELEASE_BIT,
+            pressed: code & RELEASE_BIT == 0,
+        })
+    }
+}
+
+/// Check whether the key with the given scan code is held down.
+pub fn is_held(scan_code: u8) -> bool {
+    unsafe { read_volatile(addr_of!(HELD[(scan_code & !RELEASE_BIT) as usize])) }
+}
@@ -1,27 +1,68 @@
-#![no_std]
-#![no_main]
+// the standard library is only used to run the unit tests on the host
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
+// most of the game is only reachable from `dos_main`
+#![cfg_attr(test, allow(dead_code))]
 extern crate alloc;
 
+mod anim;
+mod args;
 mod audio;
+mod bindings;
+mod border;
+mod config;
+mod font;
+mod highscore;
+mod input;
+mod irq;
+mod joystick;
+mod keyboard;
+mod menu;
+mod numbers;
+mod palette_fx;
+mod profile;
+mod save;
+mod screen;
+mod settings;
 mod stats;
+mod storage;
 mod tiles;
+mod timer;
+mod transition;
 
-use audio::{is_sound_on, play_click, play_tune, sound_off};
+use anim::{animate, Easing, SlideSpeed, PROGRESS_MAX};
+use audio::{play_click, play_tune, Tune};
+use bindings::{Action, Bindings};
+use border::Border;
 use dos_x::djgpp::dos::delay;
 use dos_x::djgpp::dpmi::{__dpmi_int, __dpmi_regs};
-use dos_x::key;
 use dos_x::vga::Palette;
+use highscore::{HighScore, HighScores, PictureId};
+use input::Controls;
+use menu::MenuChoice;
 use minipng::ImageData;
-use stats::{add_move, total_moves};
-use tiles::{Move, Tiles};
-
+use numbers::TileStyle;
+use profile::Profile;
+use save::SavedGame;
+use settings::Settings;
+use stats::{LevelStats, SessionStats};
+use tiles::{Hints, Move, Tiles};
+use transition::Transition;
+
+use alloc::format;
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::ffi::CStr;
-use core::panic::PanicInfo;
+use core::ptr::{addr_of, addr_of_mut};
+use dos_x::println;
 use dos_x::vga::vsync;
-use dos_x::{djgpp::stdlib::exit, println};
-use tinyrand::{RandRange, Seeded};
+use tinyrand::{Rand, Seeded, Xorshift};
+
+#[cfg(not(test))]
+use core::panic::PanicInfo;
+#[cfg(not(test))]
+use dos_x::djgpp::stdlib::exit;
 
 // embed images into the binary
 static IMAGE_1_DATA: &[u8] = include_bytes!("../resources/1.png");
@@ -32,36 +73,34 @@ static IMAGE_3_DATA: &[u8] = include_bytes!("../resources/3.png");
 /// (already assumes game palette for B&W)
 static FLOPPY_DATA: &[u8] = include_bytes!("../resources/floppy_16px.data");
 
+#[cfg(not(test))]
 #[no_mangle]
 fn dos_main() {
-    // process inputs
-    let mut starting_level = 0;
-    let mut seed = 1;
-    for arg in dos_x::argv() {
-        unsafe {
-            let arg = core::ffi::CStr::from_ptr(*arg);
-            if arg.to_bytes() == b"nosound" {
-                sound_off();
-            } else if arg.to_bytes() == b"iknowwhatimdoing" {
-                starting_level = 2;
-            // try to interpret it as an integer
-            } else if let Ok(s) = core::str::from_utf8(arg.to_bytes()).unwrap().parse::<u64>() {
-                // use it as a seed for the rng
-                seed = s;
-                continue;
-            } else if &md5::compute(arg.to_bytes()).0
-                == b"\xbf\x00\xed\x3c\x1a\xcc\xe2\x78\x5c\x6a\x67\xa5\x26\xf9\xfe\x14"
-            {
-                starting_level = 3;
+    // process inputs (skipping the program name)
+    let args = dos_x::argv()
+        .iter()
+        .skip(1)
+        .map(|arg| unsafe { CStr::from_ptr(*arg).to_bytes() });
+    let overrides = match args::parse(args) {
+        Ok(args::Command::Play(overrides)) => overrides,
+        Ok(args::Command::Help) => {
+            args::print_usage();
+            return;
+        }
+        Err(e) => {
+            e.print();
+            args::print_usage();
+            unsafe {
+                exit(1);
             }
+            return;
         }
-    }
+    };
 
-    let rng = tinyrand::Xorshift::seed(seed);
-    run(rng, starting_level);
+    run(overrides);
 }
 
-fn run(mut rng: impl RandRange<u16>, starting_level: u8) {
+fn run(overrides: Overrides) {
     println!("Tilers by E_net4 (2024, v1.0.0)");
 
     // disable the mouse
@@ -71,6 +110,49 @@ fn run(mut rng: impl RandRange<u16>, starting_level: u8) {
         __dpmi_int(0x33, &mut regs);
     }
 
+    // the command line takes precedence over the configuration file
+    let config = config::read().unwrap_or_default();
+    let overrides = overrides.or(Overrides::from_config(&config));
+    let mut settings = Settings::default();
+    settings.apply_config(&config);
+    if let Some(path) = &overrides.path {
+        set_level_path(path);
+    }
+    let seed = match overrides.seed {
+        Some(SeedPolicy::Fixed(seed)) => seed,
+        Some(SeedPolicy::Random) => Xorshift::seed(timer::ticks() as u64).next_u64(),
+        None => DEFAULT_SEED,
+    };
+    let mut game = Game {
+        settings: overrides.apply(settings),
+        stored_settings: settings,
+        profile: Profile::load(),
+        high_scores: HighScores::load(),
+        can_save_high_scores: true,
+        seed,
+        grid: overrides.grid,
+        report: None,
+        overrides,
+    };
+    audio::set_sound(game.settings.sound);
+    joystick::set_calibration(game.settings.joystick);
+    bindings::set_current(Bindings::load());
+    font::init();
+    timer::install();
+    keyboard::install();
+
+    let start_level = game.overrides.level.filter(|&level| {
+        if level >= count_levels() {
+            println!("There is no level {}", level + 1);
+            false
+        } else if !game.profile.is_unlocked(level) {
+            println!("Level {} is not unlocked yet", level + 1);
+            false
+        } else {
+            true
+        }
+    });
+
     play_tune();
 
     unsafe {
@@ -79,61 +161,372 @@ fn run(mut rng: impl RandRange<u16>, starting_level: u8) {
 
     println!("Loading...");
 
-    // load image for the first puzzle
-
     let mut png_buf = Vec::new();
     let mut buf = vec![0; 80_000];
 
-    let Some(mut image) = load_level_picture(0, &mut png_buf, &mut buf) else {
-        unreachable!();
+    dos_x::vga::set_video_mode_13h();
+
+    let mut scene = match start_level {
+        Some(level) => Scene::Play(Session::new(&game, level)),
+        None => Scene::Title,
     };
+    loop {
+        scene = match scene {
+            Scene::Title => {
+                match menu::title_screen(SavedGame::exists(), game.profile.has_progress()) {
+                    MenuChoice::NewGame => Scene::Play(Session::new(&game, 0)),
+                    // the saved game stays on disk until the level is completed,
+                    // in case the game is left without saving
+                    MenuChoice::Continue => match SavedGame::load() {
+                        Some(saved) => Scene::Play(Session::from(saved)),
+                        None => Scene::Title,
+                    },
+                    MenuChoice::LevelSelect => Scene::LevelSelect,
+                    MenuChoice::Options => Scene::Options,
+                    MenuChoice::HighScores => Scene::HighScores,
+                    MenuChoice::Quit => Scene::Quit,
+                }
+            }
+            Scene::LevelSelect => {
+                match menu::select_level(&game.profile, count_levels(), &mut game.settings) {
+                    Some(level) => Scene::Play(Session::new(&game, level)),
+                    None => Scene::Title,
+                }
+            }
+            Scene::Options => {
+                // change the settings of the player,
+                // leaving out what the command line overrides
+                let mut settings = game.stored_settings;
+                menu::options(&mut settings, &game.profile);
+                game.stored_settings = settings;
+                let _ = settings.save_config();
+                // then override them again for the rest of the session
+                game.settings = game.overrides.apply(settings);
+                audio::set_sound(game.settings.sound);
+                joystick::set_calibration(game.settings.joystick);
+                Scene::Title
+            }
+            Scene::HighScores => {
+                menu::high_scores(&game.high_scores, count_levels());
+                Scene::Title
+            }
+            Scene::Play(session) => play(session, &mut game, &mut png_buf, &mut buf),
+            Scene::Quit => break,
+        };
+    }
 
-    dos_x::vga::set_video_mode_13h();
+    // set back to text mode
+    unsafe {
+        dos_x::vga::set_video_mode(0x02);
+    }
+
+    if let Some(report) = &game.report {
+        report.print();
+    }
+
+    println!("Thank you for playing Tilers (2024)");
+}
+
+/// The screens of the game
+enum Scene {
+    /// The title screen with the main menu
+    Title,
+    /// The level selection screen
+    LevelSelect,
+    /// The options menu
+    Options,
+    /// The high score table
+    HighScores,
+    /// Playing the game
+    Play(Session),
+    /// Leaving the game
+    Quit,
+}
+
+/// State shared by all scenes of the game
+struct Game {
+    /// the progress of the player
+    profile: Profile,
+    /// the settings in effect
+    /// (which may be overridden by the command line)
+    settings: Settings,
+    /// the settings chosen by the player,
+    /// as kept in the configuration file
+    stored_settings: Settings,
+    /// what the command line overrides for the whole session
+    overrides: Overrides,
+    high_scores: HighScores,
+    /// stop trying to save high scores once it fails
+    /// (e.g. write protected floppy disk)
+    can_save_high_scores: bool,
+    /// the seed for new games
+    seed: u64,
+    /// the grid size of every level, if forced on the command line
+    grid: Option<(u8, u8)>,
+    /// how the last game went, to be reported on exit
+    report: Option<Report>,
+}
 
+/// The seed of new games unless configured otherwise
+const DEFAULT_SEED: u64 = 1;
+
+/// How the seed of new games is chosen
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum SeedPolicy {
+    /// always the same seed, so that games can be repeated
+    Fixed(u64),
+    /// a different seed every time the game is launched
+    Random,
+}
+
+/// Settings given through the command line
+/// (or the `[game]` section of the configuration file),
+/// which take precedence over the ones saved
+#[derive(Debug, Default, Clone)]
+struct Overrides {
+    sound: Option<bool>,
+    mode: Option<GameMode>,
+    seed: Option<SeedPolicy>,
+    /// the directory of the custom level pictures
+    path: Option<String>,
+    /// the level to start playing right away
+    level: Option<u8>,
+    /// the grid size of every level, in columns and rows
+    grid: Option<(u8, u8)>,
+}
+
+impl Overrides {
+    /// Read the `[game]` section of a configuration:
+    ///
+    /// ```none
+    /// [game]
+    /// seed = random
+    /// path = C:\PICS
+    /// ```
+    fn from_config(text: &str) -> Self {
+        let mut overrides = Overrides::default();
+        for entry in config::entries(text) {
+            if !entry.section.eq_ignore_ascii_case("game") {
+                continue;
+            }
+            if entry.key.eq_ignore_ascii_case("seed") {
+                if entry.value.eq_ignore_ascii_case("random") {
+                    overrides.seed = Some(SeedPolicy::Random);
+                } else if let Ok(seed) = entry.value.parse() {
+                    overrides.seed = Some(SeedPolicy::Fixed(seed));
+                }
+            } else if entry.key.eq_ignore_ascii_case("path") && !entry.value.is_empty() {
+                overrides.path = Some(entry.value.into());
+            }
+        }
+        overrides
+    }
+
+    /// Combine with other overrides,
+    /// keeping these where both are given.
+    fn or(self, other: Overrides) -> Overrides {
+        Overrides {
+            sound: self.sound.or(other.sound),
+            mode: self.mode.or(other.mode),
+            seed: self.seed.or(other.seed),
+            path: self.path.or(other.path),
+            level: self.level.or(other.level),
+            grid: self.grid.or(other.grid),
+        }
+    }
+
+    fn apply(&self, mut settings: Settings) -> Settings {
+        if let Some(sound) = self.sound {
+            settings.sound = sound;
+        }
+        if let Some(mode) = self.mode {
+            settings.mode = mode;
+        }
+        settings
+    }
+}
+
+/// A game being played from a given level onwards
+struct Session {
+    mode: GameMode,
+    /// the seed of the session
+    seed: u64,
+    /// the generator which shuffles the tiles of each new level in turn,
+    /// starting from the seed of the session
+    rng: Xorshift,
+    /// whether playing with the secret difficulty
+    secret: bool,
+    /// the grid size of every level, if forced on the command line
+    /// (which then earns no progress nor high scores)
+    grid: Option<(u8, u8)>,
+    /// the level being played
+    level: u8,
+    /// the statistics of the session so far
+    stats: SessionStats,
+    /// the state of the level in progress,
+    /// if continuing from a saved game
+    in_progress: Option<(Tiles, Attempt)>,
+}
+
+impl Session {
+    /// Start a new game from the given level
+    fn new(game: &Game, level: u8) -> Self {
+        Session {
+            mode: game.settings.mode,
+            seed: game.seed,
+            rng: Xorshift::seed(game.seed),
+            secret: game.settings.secret && game.profile.is_secret_unlocked(),
+            grid: game.grid,
+            level,
+            stats: SessionStats::new(),
+            in_progress: None,
+        }
+    }
+}
+
+impl From<SavedGame> for Session {
+    fn from(saved: SavedGame) -> Self {
+        // bring the generator back to where it was
+        // when the level in progress began
+        let mut rng = Xorshift::seed(saved.seed);
+        if let Some((_, earlier)) = saved.stats.levels.split_last() {
+            for l in earlier {
+                shuffle_level((l.cols, l.rows), &mut rng);
+            }
+        }
+        Session {
+            mode: saved.mode,
+            seed: saved.seed,
+            rng,
+            secret: saved.secret,
+            grid: saved.grid,
+            level: saved.level,
+            stats: saved.stats,
+            in_progress: Some((saved.tiles, saved.attempt)),
+        }
+    }
+}
+
+/// How a game went, to be reported when leaving
+struct Report {
+    stats: SessionStats,
+    /// the last level reached
+    level: u8,
+    /// whether all levels were completed
+    win: bool,
+    /// whether the secret difficulty was earned in this game
+    secret_earned: bool,
+    /// whether the game ended by running out of time
+    time_up: bool,
+}
+
+impl Report {
+    /// Print the report (in text mode)
+    fn print(&self) {
+        if self.win {
+            println!("Congratulations! You have completed the game!");
+            if self.level == 3 {
+                println!("Or have you? Consider installing more levels!");
+            }
+            if self.secret_earned {
+                println!("You have earned the secret difficulty!");
+                println!("Enable it in the options next time you play.");
+            }
+        }
+
+        if self.time_up {
+            println!("Time is up! You made it to level {}.", self.level + 1);
+        }
+
+        if self.stats.total_moves() > 4 {
+            self.stats.print_results();
+        }
+    }
+}
+
+/// Play the game from the session's level onwards,
+/// until the player quits, fails, or completes all levels.
+fn play(mut session: Session, game: &mut Game, png_buf: &mut Vec<u8>, buf: &mut [u8]) -> Scene {
     // grab palette and apply it to VGA display
     let mut palette = Palette::new([0u8; 768]);
 
+    // a new game takes the place of the saved one
+    if session.in_progress.is_none() {
+        SavedGame::delete();
+    }
+
     let mut win = false;
-    let mut level = starting_level;
+    let mut secret_earned = false;
+    let mut time_up = false;
+    // the HUD can be toggled while playing
+    let hud = game.settings.hud;
     loop {
+        let level = session.level;
         unsafe {
-            vsync();
-            dos_x::vga::draw_rect(0, 0, 320, 200, 255);
             // ensure that black (255) and white (254) is in the palette
             dos_x::vga::set_color_single(0xFE, 63, 63, 63);
             dos_x::vga::set_color_single(0xFF, 0, 0, 0);
-            // for the first level we load the game in text mode
-            // to let the player see the introductory text.
-            // for the remaining levels we show a loading screen in video mode.
-            if level > 0 {
-                // draw floppy disk onto the screen
-                // (suggesting that the game is loading)
-                dos_x::vga::blit_rect(FLOPPY_DATA, (16, 16), (0, 0, 16, 16), (152, 92));
-            }
-        }
-
-        if level > 0 {
-            // load the next image
-            image = match load_level_picture(level, &mut png_buf, &mut buf) {
-                Some(img) => img,
-                None => {
-                    win = true;
-                    break;
-                }
-            }
+            dos_x::vga::set_color_single(TIMER_BAR_COLOR, 63, 48, 0);
         }
+        screen::draw_rect(0, 0, 320, 200, 255);
+        // draw floppy disk onto the screen
+        // (suggesting that the game is loading)
+        screen::blit_rect(FLOPPY_DATA, (16, 16), (0, 0, 16, 16), (152, 92));
+        font::draw_text_centered(116, &format!("Level {}", level + 1), 0xFE);
+        screen::present();
+
+        // load the next image
+        let Some(loaded) = load_level_picture(level, png_buf, buf) else {
+            win = true;
+            // completing all levels earns the secret difficulty
+            // (unless the grid size is forced)
+            if session.grid.is_none() && game.profile.unlock_secret() {
+                secret_earned = true;
+                let _ = game.profile.save();
+            }
+            break;
+        };
 
-        // set up palette
-        let mut k = 0;
-        for i in 0..=254 {
-            let [r, g, b, _a] = image.palette(i);
-            palette.0[k] = r >> 2;
-            k += 1;
-            palette.0[k] = g >> 2;
-            k += 1;
-            palette.0[k] = b >> 2;
-            k += 1;
-        }
+        // a saved game goes on with the grid size it was saved with
+        let (cols, rows) = match &session.in_progress {
+            Some((tiles, _)) => (tiles.cols, tiles.rows),
+            None => session
+                .grid
+                .unwrap_or_else(|| grid_size(level, session.secret)),
+        };
+
+        // set up the picture and the palette,
+        // falling back to numbered tiles if the picture cannot be used
+        let (pixels, picture_id) = match loaded {
+            Ok((image, picture_id)) if game.settings.tile_style != TileStyle::Numbers => {
+                let mut k = 0;
+                for i in 0..=254 {
+                    let [r, g, b, _a] = image.palette(i);
+                    palette.0[k] = r >> 2;
+                    k += 1;
+                    palette.0[k] = g >> 2;
+                    k += 1;
+                    palette.0[k] = b >> 2;
+                    k += 1;
+                }
+                (image.pixels().to_vec(), picture_id)
+            }
+            loaded => {
+                if let Err(e) = loaded {
+                    let filename = level_filename(level);
+                    let filename = CStr::from_bytes_until_nul(&filename).unwrap();
+                    let message = format!("{}: {}", filename.to_str().unwrap_or("?"), e);
+                    font::draw_banner(140, &message, 0xFE);
+                    font::draw_banner(156, "Playing with numbers instead", 0xFE);
+                    screen::present();
+                    unsafe {
+                        delay(2_000);
+                    }
+                }
+                numbers::set_palette(&mut palette);
+                (numbers::render(cols, rows), numbers::picture_id(cols, rows))
+            }
+        };
         // ensure that the last color (#255) is always black.
         palette.0[765] = 0;
         palette.0[766] = 0;
@@ -142,77 +535,354 @@ fn run(mut rng: impl RandRange<u16>, starting_level: u8) {
         palette.0[762] = 63;
         palette.0[763] = 63;
         palette.0[764] = 63;
-        palette.set();
+        // the next colors are only taken from the picture
+        // when the feature drawing with them is enabled.
+        // the third last color (#253) is for the countdown bar
+        if session.mode != GameMode::Classic {
+            palette.0[759] = 63;
+            palette.0[760] = 48;
+            palette.0[761] = 0;
+        }
+        // the fourth last color (#252) is for highlighting tiles in place
+        if game.settings.highlight {
+            palette.0[756] = 16;
+            palette.0[757] = 63;
+            palette.0[758] = 16;
+        }
+        // the next two (#251 and #250) are for the borders of the tiles
+        // (the lighter one only for bevels)
+        if game.settings.border == Border::Bevel {
+            palette.0[753] = 56;
+            palette.0[754] = 56;
+            palette.0[755] = 56;
+        }
+        if game.settings.border != Border::None {
+            palette.0[750] = 8;
+            palette.0[751] = 8;
+            palette.0[752] = 8;
+        }
 
-        match game_level(&mut rng, level, &image.pixels()) {
-            LevelOutcome::Exit => break,
-            LevelOutcome::NextLevel => {
-                // paint the whole picture without the empty slot
-                unsafe {
-                    vsync();
-                    dos_x::vga::draw_buffer(&image.pixels());
-                }
-                unsafe {
-                    if is_sound_on() {
-                        play_tune();
-                        delay(500);
-                    } else {
-                        delay(2_000);
+        // bring in the picture of the level from black
+        Palette::new([0; 768]).set();
+        screen::draw_buffer(&pixels);
+        screen::present();
+        palette_fx::fade_in(&palette, FADE_MILLIS);
+
+        let initial_tiles = shuffle_level((cols, rows), &mut session.rng);
+
+        let (mut tiles, mut attempt) = match session.in_progress.take() {
+            // continue where the saved game left off
+            Some(progress) => progress,
+            None => {
+                let optimal_moves = if cols as u32 * rows as u32 <= 12 {
+                    initial_tiles.optimal_moves(OPTIMAL_SEARCH_MAX_NODES)
+                } else {
+                    None
+                };
+                session
+                    .stats
+                    .start_level(level, (cols, rows), session.seed, optimal_moves);
+                (initial_tiles.clone(), Attempt::default())
+            }
+        };
+        let (level_stats, previous) = session.stats.levels.split_last_mut().unwrap();
+        let setup = LevelSetup {
+            level,
+            picture: &pixels,
+            mode: session.mode,
+            previous_moves: previous.iter().map(|l| l.moves).sum(),
+            can_peek: !game.settings.hardcore,
+            animation: (game.settings.slide_speed, game.settings.easing),
+            transition: game
+                .settings
+                .transition
+                .pick(&mut Xorshift::seed(session.seed ^ level as u64)),
+            overlay: Overlay {
+                highlight: game.settings.highlight,
+                numbers: game.settings.tile_style == TileStyle::PictureNumbered,
+            },
+            border: game.settings.border,
+            controls: game.settings.controls,
+            invert: game.settings.invert,
+        };
+
+        let outcome = loop {
+            match game_level(
+                &mut tiles,
+                &mut attempt,
+                &setup,
+                level_stats,
+                &mut game.settings.hud,
+            ) {
+                LevelOutcome::Failed => {
+                    // let the player see the message for a moment,
+                    // then retry with the same disposition of tiles
+                    font::draw_banner(96, "Out of moves! Try again", 0xFE);
+                    screen::present();
+                    unsafe {
+                        delay(1_000);
                     }
+                    tiles = initial_tiles.clone();
+                    attempt = Attempt::default();
                 }
+                outcome => break outcome,
+            }
+        };
 
-                level += 1;
-
-                // fade out
-                for _ in 0..64 {
-                    unsafe {
-                        for p in palette.0.iter_mut().take(248 * 3) {
-                            *p = p.saturating_sub(1);
+        match outcome {
+            LevelOutcome::Exit => {
+                font::draw_banner(96, "Save the game before leaving? (Y/N)", 0xFE);
+                screen::present();
+                if input::wait_yes_no() {
+                    let saved = SavedGame {
+                        mode: session.mode,
+                        seed: session.seed,
+                        secret: session.secret,
+                        grid: session.grid,
+                        level,
+                        tiles,
+                        attempt,
+                        stats: session.stats.clone(),
+                    };
+                    if saved.save().is_err() {
+                        font::draw_banner(112, "Could not save the game!", 0xFE);
+                        screen::present();
+                        unsafe {
+                            delay(2_000);
                         }
-                        vsync();
-                        palette.set();
                     }
                 }
+                break;
+            }
+            LevelOutcome::TimeUp => {
+                font::draw_banner(96, "Time is up!", 0xFE);
+                screen::present();
+                unsafe {
+                    delay(2_000);
+                }
+                time_up = true;
+                break;
+            }
+            LevelOutcome::Failed => unreachable!(),
+            LevelOutcome::NextLevel => {
+                // the saved game is behind now
+                SavedGame::delete();
+                // a forced grid size does not count towards high scores nor progress
+                let forced = session.grid.is_some();
+                let record = !forced
+                    && game.high_scores.submit(
+                        &picture_id,
+                        (cols, rows),
+                        level_stats.moves,
+                        level_stats.ticks,
+                    );
+                if record && game.can_save_high_scores && game.high_scores.save().is_err() {
+                    game.can_save_high_scores = false;
+                }
+                if !forced && game.profile.complete(level) {
+                    let _ = game.profile.save();
+                }
+
+                // paint the whole picture (without the HUD)
+                screen::draw_buffer(&pixels);
+                celebrate(&palette, || {
+                    show_level_complete(
+                        level_stats,
+                        game.high_scores
+                            .get(&picture_id, (cols, rows))
+                            .filter(|_| !forced),
+                        record,
+                        game.can_save_high_scores && !forced,
+                    );
+                });
+
+                session.level += 1;
+
+                palette_fx::fade_out(&palette, FADE_MILLIS);
+                palette.0 = [0; 768];
             }
         }
     }
 
-    // fade out
+    // fade out (unless it already happened at the end of the level)
+    if palette.0 != [0; 768] {
+        palette_fx::fade_out(&palette, FADE_MILLIS);
+    }
 
-    for _ in 0..64 {
-        unsafe {
-            for p in palette.0.iter_mut().take(248 * 3) {
-                *p = p.saturating_sub(1);
+    // remember whether the HUD was toggled during the game
+    if game.settings.hud != hud {
+        game.stored_settings.hud = game.settings.hud;
+        let _ = game.stored_settings.save_config_option("hud");
+    }
+
+    game.report = Some(Report {
+        stats: session.stats,
+        level: session.level,
+        win,
+        secret_earned,
+        time_up,
+    });
+
+    Scene::Title
+}
+
+/// Celebrate a solved puzzle:
+/// flash the screen, then show the banners drawn by `show_banners`
+/// while the tune plays.
+///
+/// Any key skips to the end.
+fn celebrate(palette: &Palette, show_banners: impl FnOnce()) {
+    // discard keys pressed while solving the puzzle
+    input::flush();
+
+    let mut tune = Tune::start();
+    let mut show_banners = Some(show_banners);
+    let start = timer::millis();
+    loop {
+        let elapsed = timer::millis().wrapping_sub(start);
+        screen::present();
+        palette_fx::flash_at(palette, elapsed, FLASH_MILLIS);
+        if elapsed >= FLASH_MILLIS {
+            if let Some(show_banners) = show_banners.take() {
+                show_banners();
             }
-            vsync();
-            palette.set();
+        }
+
+        let playing = tune.update();
+        if input::poll_key().is_some() || (!playing && elapsed >= CELEBRATION_MILLIS) {
+            break;
         }
     }
 
-    // set back to text mode
+    tune.stop();
+    palette.set();
+    if let Some(show_banners) = show_banners.take() {
+        show_banners();
+        screen::present();
+    }
+}
+
+/// Show the level statistics and best scores over the solved picture.
+fn show_level_complete(
+    stats: &LevelStats,
+    best: Option<HighScore>,
+    record: bool,
+    high_scores_saved: bool,
+) {
+    font::draw_banner(136, &format!("Level {} complete!", stats.level + 1), 0xFE);
+
+    let seconds = timer::ticks_to_seconds(stats.ticks);
+    font::draw_banner(
+        152,
+        &format!(
+            "{} moves in {}:{:02}",
+            stats.moves,
+            seconds / 60,
+            seconds % 60
+        ),
+        0xFE,
+    );
+
+    if let Some(best) = best {
+        let seconds = timer::ticks_to_seconds(best.ticks);
+        let message = if record {
+            format!(
+                "New record! Best: {}, {}:{:02}",
+                best.moves,
+                seconds / 60,
+                seconds % 60
+            )
+        } else {
+            format!(
+                "Best: {} moves, {}:{:02}",
+                best.moves,
+                seconds / 60,
+                seconds % 60
+            )
+        };
+        font::draw_banner(168, &message, 0xFE);
+    }
+
+    if !high_scores_saved {
+        font::draw_banner(184, "(high scores not saved)", 0xFE);
+    }
+    screen::present();
+}
+
+/// The directory of the custom level pictures
+/// (empty for the current directory)
+static mut LEVEL_PATH: String = String::new();
+
+/// Change the directory of the custom level pictures.
+fn set_level_path(path: &str) {
     unsafe {
-        dos_x::vga::set_video_mode(0x02);
+        *addr_of_mut!(LEVEL_PATH) = path.into();
     }
+}
 
-    if win {
-        println!("Congratulations! You have completed the game!");
-        if level == 3 {
-            println!("Or have you? Consider installing more levels!");
-        }
+/// Build the file name of the custom picture for a level,
+/// within the directory of custom pictures
+/// (a nul terminated string)
+fn level_filename(level: u8) -> Vec<u8> {
+    let path = unsafe { &*addr_of!(LEVEL_PATH) };
+    let mut full = Vec::with_capacity(path.len() + 8);
+    full.extend_from_slice(path.as_bytes());
+    if !path.is_empty() && !path.ends_with(['\\', '/', ':']) {
+        full.push(b'\\');
+    }
+    let mut filename = *b"#.png\0\0";
+    // write the file name to the buffer above
+    let l = level + 1;
+    if l < 10 {
+        filename[0] = b'0' + l;
+    } else {
+        filename = *b"##.png\0";
+        filename[0] = b'0' + (l / 10);
+        filename[1] = b'0' + (l % 10);
     }
+    full.extend_from_slice(&filename);
+    full
+}
 
-    let total_moves = total_moves();
-    if total_moves > 4 {
-        println!("The tiles were moved {} times in total.", total_moves);
+/// Count the number of levels available,
+/// embedded or custom.
+fn count_levels() -> u8 {
+    let mut count = 3;
+    while count < 99 {
+        let filename = level_filename(count);
+        let cfilename = CStr::from_bytes_until_nul(&filename).unwrap();
+        if dos_x::fs::File::open(cfilename).is_err() {
+            break;
+        }
+        count += 1;
     }
+    count
+}
 
-    println!("Thank you for playing Tilers (2024)");
+/// Calculate the identifier of the picture for a given level
+/// without decoding it.
+fn level_picture_id(level: u8) -> Option<PictureId> {
+    let filename = level_filename(level);
+    let cfilename = CStr::from_bytes_until_nul(&filename).unwrap();
+    if let Ok(mut file) = dos_x::fs::File::open(cfilename) {
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).ok()?;
+        return Some(md5::compute(&data).0);
+    }
+    match level {
+        0 => Some(md5::compute(IMAGE_1_DATA).0),
+        1 => Some(md5::compute(IMAGE_2_DATA).0),
+        2 => Some(md5::compute(IMAGE_3_DATA).0),
+        _ => None,
+    }
 }
 
-/// Load the picture for a given level.
+/// Load the picture for a given level,
+/// along with an identifier of the picture file.
 ///
 /// First it looks for a file named "#.png" where # is the level number
-/// (starting from 1 instead of 0).
+/// (starting from 1 instead of 0),
+/// in the directory of custom pictures.
 /// If the PNG is OK, we're done!
 /// Otherwise, grab the default picture for the level
 /// from the embedded resources,
@@ -221,43 +891,24 @@ fn load_level_picture<'a>(
     level: u8,
     png_buffer: &mut Vec<u8>,
     img_buffer: &'a mut [u8],
-) -> Option<ImageData<'a>> {
+) -> Option<Result<(ImageData<'a>, PictureId), PictureError>> {
     if level >= 99 {
         return None;
     }
 
     let number = level + 1;
 
-    let mut filename = *b"#.png\0\0";
-    // write the file name to the buffer above
-    let l = level + 1;
-    if l < 10 {
-        filename[0] = b'0' + l;
-    } else {
-        filename = *b"##.png\0";
-        filename[0] = b'0' + (l / 10);
-        filename[1] = b'0' + (l % 10);
-    }
-
+    let filename = level_filename(level);
     let cfilename = CStr::from_bytes_until_nul(&filename).unwrap();
 
     let file = dos_x::fs::File::open(cfilename);
 
     let (pic_data, custom) = if let Ok(mut file) = file {
         png_buffer.clear();
-        match file.read_to_end(png_buffer) {
-            Ok(_) => (&png_buffer[..], true),
-            Err(e) => {
-                unsafe {
-                    dos_x::vga::set_video_mode(0x02);
-                }
-                println!("Error: Failed to read custom image file: {}", e);
-                unsafe {
-                    exit(2);
-                    unreachable!();
-                }
-            }
+        if file.read_to_end(png_buffer).is_err() {
+            return Some(Err(PictureError::Read));
         }
+        (&png_buffer[..], true)
     } else {
         (
             match number {
@@ -270,45 +921,46 @@ fn load_level_picture<'a>(
         )
     };
 
-    match minipng::decode_png(pic_data, img_buffer) {
-        Ok(image) => {
-            // validate
-            if custom {
-                if image.width() != 320 || image.height() != 200 {
-                    unsafe {
-                        dos_x::vga::set_video_mode(0x02);
-                    }
-                    println!("Error: Custom image must be 320x200 pixels");
-                    unsafe {
-                        exit(2);
-                        unreachable!();
-                    }
-                }
-                if image.color_type() != minipng::ColorType::Indexed {
-                    unsafe {
-                        dos_x::vga::set_video_mode(0x02);
-                    }
-                    println!("Error: Custom image must be indexed");
-                    unsafe {
-                        exit(2);
-                        unreachable!();
-                    }
-                }
-            }
+    let picture_id = md5::compute(pic_data).0;
 
-            Some(image)
+    let Ok(image) = minipng::decode_png(pic_data, img_buffer) else {
+        return Some(Err(PictureError::Decode));
+    };
+    // validate
+    if custom {
+        if image.width() != 320 || image.height() != 200 {
+            return Some(Err(PictureError::Size));
         }
-        Err(e) => {
-            unsafe {
-                dos_x::vga::set_video_mode(0x02);
-            }
-            println!("Error: Could not decode PNG file: {}", e);
-            unsafe {
-                exit(2);
-                unreachable!();
-            }
+        if image.color_type() != minipng::ColorType::Indexed {
+            return Some(Err(PictureError::NotIndexed));
         }
     }
+
+    Some(Ok((image, picture_id)))
+}
+
+/// Why the picture of a level cannot be used
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum PictureError {
+    /// the custom picture file could not be read
+    Read,
+    /// the picture is not a valid PNG file
+    Decode,
+    /// the custom picture is not 320x200 pixels
+    Size,
+    /// the custom picture does not have a color palette
+    NotIndexed,
+}
+
+impl core::fmt::Display for PictureError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            PictureError::Read => "could not read file",
+            PictureError::Decode => "not a valid PNG",
+            PictureError::Size => "must be 320x200",
+            PictureError::NotIndexed => "must be indexed",
+        })
+    }
 }
 
 /// What the game should do as the level ends
@@ -320,99 +972,692 @@ enum LevelOutcome {
     /// Proceed to the next level
     /// (or just end with a congratulatory message if there are no more levels)
     NextLevel,
+    /// The time budget for the level ran out,
+    /// which ends the run
+    TimeUp,
+    /// The move budget for the level ran out,
+    /// so the level should be retried
+    Failed,
 }
 
-fn game_level(rng: &mut impl RandRange<u16>, level: u8, picture: &[u8]) -> LevelOutcome {
-    let (cols, rows) = match level {
+/// The rules under which the game is played
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+enum GameMode {
+    /// Solve the puzzles at your own pace
+    Classic,
+    /// Each level must be solved before its time budget runs out
+    TimeAttack,
+    /// Each level must be solved within a limited number of moves
+    MoveLimit,
+}
+
+impl GameMode {
+    /// The name of the game mode, as shown in the options
+    fn name(self) -> &'static str {
+        match self {
+            GameMode::Classic => "Classic",
+            GameMode::TimeAttack => "Time attack",
+            GameMode::MoveLimit => "Move limit",
+        }
+    }
+
+    /// The game mode after this one in the options
+    fn next(self) -> Self {
+        match self {
+            GameMode::Classic => GameMode::TimeAttack,
+            GameMode::TimeAttack => GameMode::MoveLimit,
+            GameMode::MoveLimit => GameMode::Classic,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(GameMode::Classic),
+            1 => Some(GameMode::TimeAttack),
+            2 => Some(GameMode::MoveLimit),
+            _ => None,
+        }
+    }
+}
+
+/// Progress within the current attempt at a level
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+struct Attempt {
+    /// the number of tile moves in this attempt
+    moves: u32,
+    /// the time spent in this attempt, in BIOS ticks
+    ticks: u32,
+}
+
+/// The maximum number of states to visit
+/// when searching for the optimal solution of a puzzle,
+/// kept low since the search runs before the board is shown
+/// (bigger puzzles go without)
+const OPTIMAL_SEARCH_MAX_NODES: u32 = 20_000;
+
+/// The maximum number of states to visit
+/// when searching for an optimal solution to give hints from,
+/// kept low so that the game does not stall
+const HINT_MAX_NODES: u32 = 20_000;
+
+/// Palette color reserved for the countdown bar
+/// (time or moves remaining)
+const TIMER_BAR_COLOR: u8 = 0xFD;
+
+/// Duration of palette fades in milliseconds
+const FADE_MILLIS: u32 = 900;
+
+/// Duration of the flash on solving a puzzle in milliseconds
+const FLASH_MILLIS: u32 = 300;
+
+/// Minimum duration of the celebration of a solved puzzle in milliseconds
+/// (if the tune ends sooner)
+const CELEBRATION_MILLIS: u32 = 2_000;
+
+/// Palette color reserved for outlining the tiles in their home position
+/// and for the completion meter
+const HIGHLIGHT_COLOR: u8 = 0xFC;
+
+/// Height of the completion meter in pixels
+const METER_HEIGHT: u32 = 2;
+
+/// Palette color of the cursor (white)
+const CURSOR_COLOR: u8 = 0xFE;
+
+/// Width of the frame of the cursor in pixels
+const CURSOR_THICKNESS: u32 = 2;
+
+/// Height of the countdown bar in pixels
+const TIMER_BAR_HEIGHT: u32 = 2;
+
+/// The time given to solve a puzzle in time attack mode, in BIOS ticks.
+fn time_budget(cols: u8, rows: u8) -> u32 {
+    let seconds = match (cols, rows) {
+        (3, 2) => 30,
+        (4, 3) => 120,
+        (5, 4) => 300,
+        _ => 900,
+    };
+    timer::seconds_to_ticks(seconds)
+}
+
+/// The number of moves given to solve a puzzle in move limit mode.
+///
+/// Based on the optimal solution if known
+/// (or a lower bound of it otherwise),
+/// plus some slack which grows with the size of the puzzle.
+fn move_budget(tiles: &Tiles, optimal_moves: Option<u32>) -> u32 {
+    let slack = tiles.cols as u32 * tiles.rows as u32 * 2;
+    match optimal_moves {
+        Some(moves) => moves + slack,
+        None => tiles.min_moves() * 3 + slack,
+    }
+}
+
+/// Shuffle the tiles of a new level
+/// with the generator of the session,
+/// so that the same seed always gives the same boards.
+fn shuffle_level((cols, rows): (u8, u8), rng: &mut Xorshift) -> Tiles {
+    Tiles::new_shuffled(cols, rows, rng, 512)
+}
+
+/// The dimensions of the puzzle (columns and rows) for the given level
+fn grid_size(level: u8, secret: bool) -> (u8, u8) {
+    match level {
+        // secret difficulty
+        _ if secret => (8, 5),
         0 => (3, 2),
         1 => (4, 3),
         2..=8 => (5, 4),
-        // secret difficulty
         _ => (8, 5),
-    };
+    }
+}
+
+/// Height of the heads-up display strip at the bottom of the screen
+const HUD_HEIGHT: u32 = 12;
+
+/// Where and how large the puzzle is drawn on the screen
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Layout {
+    /// height of the screen area for the puzzle
+    /// (everything above the HUD or the countdown bar, if visible)
+    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    /// offset in pixels so that the puzzle stays centered
+    offset_x: u32,
+    offset_y: u32,
+    /// what is drawn around each tile
+    border: Border,
+}
+
+impl Layout {
+    fn new(cols: u8, rows: u8, hud: bool, countdown: bool, border: Border) -> Self {
+        // the HUD leaves room for the countdown bar above it
+        let height = if hud {
+            200 - HUD_HEIGHT
+        } else if countdown {
+            200 - TIMER_BAR_HEIGHT
+        } else {
+            200
+        };
+        let tile_width = 320 / cols as u32;
+        let tile_height = height / rows as u32;
+        Layout {
+            height,
+            tile_width,
+            tile_height,
+            offset_x: (320 - tile_width * cols as u32) / 2,
+            offset_y: (height - tile_height * rows as u32) / 2,
+            border,
+        }
+    }
+
+    /// The part of a tile showing the picture,
+    /// inside the gutter left for the border
+    /// (x, y relative to the tile, width, height)
+    fn tile_face(&self) -> (u32, u32, u32, u32) {
+        let gutter = self.border.gutter();
+        (
+            gutter,
+            gutter,
+            self.tile_width - gutter * 2,
+            self.tile_height - gutter * 2,
+        )
+    }
+
+    /// Draw the tile whose top left corner is at the first position in the picture
+    /// onto the screen at the second position,
+    /// along with its border.
+    fn draw_tile_at(&self, picture: &[u8], (origin_x, origin_y): (u32, u32), (x, y): (i32, i32)) {
+        let (face_x, face_y, face_width, face_height) = self.tile_face();
+        let origin = (
+            origin_x + face_x,
+            origin_y + face_y,
+            face_width,
+            face_height,
+        );
+        let target = (x + face_x as i32, y + face_y as i32);
+        screen::blit_rect(picture, self.picture_size(), origin, target);
+        self.border.draw(x, y, self.tile_width, self.tile_height);
+    }
+
+    /// The dimensions of the picture as drawn on the screen
+    fn picture_size(&self) -> (u32, u32) {
+        (320, self.height)
+    }
+
+    /// Whether the picture has to be resampled to fit the puzzle area
+    fn is_scaled(&self) -> bool {
+        self.height < 200
+    }
+
+    /// Obtain the expected x,y coordinates in pixels of a grid position
+    #[inline]
+    fn pixel_position(&self, col: u8, row: u8) -> (u32, u32) {
+        (
+            self.offset_x + col as u32 * self.tile_width,
+            self.offset_y + row as u32 * self.tile_height,
+        )
+    }
+
+    /// The rectangles of all grid positions on the screen
+    /// (x, y, width, height)
+    fn tile_rects(&self, cols: u8, rows: u8) -> Vec<(u32, u32, u32, u32)> {
+        let mut rects = Vec::with_capacity(cols as usize * rows as usize);
+        for row in 0..rows {
+            for col in 0..cols {
+                let (x, y) = self.pixel_position(col, row);
+                rects.push((x, y, self.tile_width, self.tile_height));
+            }
+        }
+        rects
+    }
+
+    /// Obtain the expected x,y coordinates in pixels of a grid position
+    #[inline]
+    fn pixel_position_i32(&self, col: u8, row: u8) -> (i32, i32) {
+        let (x, y) = self.pixel_position(col, row);
+        (x as i32, y as i32)
+    }
+}
+
+/// Resample the picture (320x200) vertically to the given height,
+/// so that it fits the area left for the puzzle.
+fn scale_picture(picture: &[u8], height: u32) -> Vec<u8> {
+    let mut scaled = Vec::with_capacity(320 * height as usize);
+    for y in 0..height {
+        let source_y = (y * 200 / height) as usize;
+        scaled.extend_from_slice(&picture[source_y * 320..(source_y + 1) * 320]);
+    }
+    scaled
+}
+
+/// Everything about a level which stays the same while it is played
+struct LevelSetup<'a> {
+    level: u8,
+    /// the full picture of the level (320x200)
+    picture: &'a [u8],
+    mode: GameMode,
+    /// moves done in the previous levels of the session
+    previous_moves: u32,
+    /// whether the solved picture can be previewed
+    /// and hints can be asked for
+    can_peek: bool,
+    /// how tiles slide
+    animation: (SlideSpeed, Easing),
+    /// how the board appears
+    transition: Transition,
+    /// what to draw over the tiles
+    overlay: Overlay,
+    /// what to draw around the tiles
+    border: Border,
+    /// whether the arrow keys move tiles or a cursor
+    controls: Controls,
+    /// whether the arrow keys move the empty slot instead of a tile
+    invert: bool,
+}
 
-    // decide the total width & height of the puzzle
-    // as well as the size of the pieces
-    let (width, tile_width) = (320, 320 / cols as u32);
-    let (height, tile_height) = if rows == 3 {
-        (198, 198 / rows as u32)
+/// Assists drawn over the tiles
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+struct Overlay {
+    /// outline the tiles in their home position
+    highlight: bool,
+    /// show the number of each tile in its corner
+    numbers: bool,
+}
+
+fn game_level(
+    tiles: &mut Tiles,
+    attempt: &mut Attempt,
+    setup: &LevelSetup,
+    stats: &mut LevelStats,
+    hud: &mut bool,
+) -> LevelOutcome {
+    let (cols, rows) = (tiles.cols, tiles.rows);
+
+    // decide the size of the puzzle and of its pieces,
+    // leaving room for the HUD and the countdown bar if visible
+    let countdown = matches!(setup.mode, GameMode::TimeAttack | GameMode::MoveLimit);
+    let mut layout = Layout::new(cols, rows, *hud, countdown, setup.border);
+    let mut scaled_picture = if layout.is_scaled() {
+        scale_picture(setup.picture, layout.height)
     } else {
-        (200, 200 / rows as u32)
+        Vec::new()
     };
 
-    let picture = &picture[0..width as usize * height as usize];
+    {
+        let picture = if layout.is_scaled() {
+            &scaled_picture[..]
+        } else {
+            setup.picture
+        };
+        match setup.transition {
+            Transition::TileByTile => draw_tiles_animated(tiles, picture, &layout, setup.overlay),
+            transition => {
+                // compose the board off screen,
+                // then bring it in over what is shown now
+                let from = screen::snapshot();
+                screen::draw_rect(0, 0, 320, 200, 255);
+                draw_tiles(tiles, picture, &layout, setup.overlay);
+                let to = screen::snapshot();
+                screen::draw_buffer(&from);
+                transition::run(transition, &from, &to, &layout.tile_rects(cols, rows));
+            }
+        }
+    }
 
-    let mut tiles = Tiles::new_shuffled(cols, rows, &mut *rng, 512);
+    // discard keys pressed during the intro
+    input::flush();
 
-    unsafe {
-        vsync();
+    let time_budget = match setup.mode {
+        GameMode::TimeAttack => Some(time_budget(cols, rows)),
+        _ => None,
+    };
+    let move_budget = match setup.mode {
+        GameMode::MoveLimit => Some(move_budget(tiles, stats.optimal_moves)),
+        _ => None,
+    };
+    if let Some(budget) = move_budget {
+        draw_countdown_bar(budget.saturating_sub(attempt.moves), budget, &layout);
+    }
+    if setup.overlay.highlight {
+        draw_completion_meter(tiles);
     }
 
-    draw_tiles_animated(&tiles, picture, (width, height), (tile_width, tile_height));
+    // the grid position selected with the cursor controls,
+    // starting at the empty slot
+    let mut cursor = (tiles.empty_x, tiles.empty_y);
+    if setup.controls == Controls::Cursor {
+        draw_cursor(&layout, cursor);
+    }
+
+    // the moves made so far, to be undone in reverse order
+    let mut history: Vec<Move> = Vec::new();
+    let mut hints = Hints::default();
+
+    let mut last_tick = timer::ticks();
+    // the text last drawn in the HUD, to avoid redrawing it every frame
+    let mut hud_text = String::new();
 
     loop {
-        // - implement user input & tile movements
+        // show whatever changed in the previous iteration
+        screen::present();
+
+        // keep track of the time spent
+        let now = timer::ticks();
+        let elapsed = timer::ticks_between(last_tick, now);
+        last_tick = now;
+        attempt.ticks += elapsed;
+        stats.ticks += elapsed;
+
+        // update the countdown
+        if let Some(budget) = time_budget {
+            if attempt.ticks >= budget {
+                draw_countdown_bar(0, budget, &layout);
+                return LevelOutcome::TimeUp;
+            }
+            draw_countdown_bar(budget - attempt.ticks, budget, &layout);
+        }
+
+        if *hud {
+            let moves_left = move_budget.map(|budget| budget.saturating_sub(attempt.moves));
+            let text = hud_text_for(tiles, setup, stats, moves_left);
+            if text != hud_text {
+                draw_hud(&text, &layout);
+                hud_text = text;
+            }
+        }
+
+        let Some((key, action)) = input::poll_action() else {
+            continue;
+        };
+
+        // implement exit (ESC unless rebound)
+        if action == Some(Action::Quit) {
+            return LevelOutcome::Exit;
+        }
+
+        let mut redraw = false;
+
+        // toggle the HUD (Tab)
+        if key == 0x0f {
+            *hud = !*hud;
+            layout = Layout::new(cols, rows, *hud, countdown, setup.border);
+            scaled_picture = if layout.is_scaled() {
+                scale_picture(setup.picture, layout.height)
+            } else {
+                Vec::new()
+            };
+            redraw = true;
+        }
+
+        // preview the solved picture while the key is held (Space by default)
+        // (not in hardcore mode)
+        if action == Some(Action::Peek) && setup.can_peek {
+            stats.peeks += 1;
+            screen::draw_buffer(setup.picture);
+            screen::present();
+            while input::is_key_held(key) {
+                unsafe {
+                    vsync();
+                }
+            }
+            // discard the repeated key presses
+            input::flush();
+            redraw = true;
+        }
+
+        // stop everything until another key is pressed,
+        // leaving the time paused out of the count
+        if action == Some(Action::Pause) {
+            font::draw_banner(96, "Paused, press any key", 0xFE);
+            screen::present();
+            input::flush();
+            while input::poll_key().is_none() {
+                unsafe {
+                    vsync();
+                }
+            }
+            last_tick = timer::ticks();
+            redraw = true;
+        }
+
+        // bring back the current state of the board
+        if redraw {
+            hud_text.clear();
+            let picture = if layout.is_scaled() {
+                &scaled_picture[..]
+            } else {
+                setup.picture
+            };
+            screen::draw_rect(0, 0, 320, 200, 255);
+            draw_tiles(tiles, picture, &layout, setup.overlay);
+            if let Some(budget) = move_budget {
+                draw_countdown_bar(budget.saturating_sub(attempt.moves), budget, &layout);
+            }
+            if setup.overlay.highlight {
+                draw_completion_meter(tiles);
+            }
+            if setup.controls == Controls::Cursor {
+                draw_cursor(&layout, cursor);
+            }
+            continue;
+        }
+
+        let picture = if layout.is_scaled() {
+            &scaled_picture[..]
+        } else {
+            setup.picture
+        };
 
         // detect Left, Right, Up, Down key presses
-        // (also W, A, S, D)
-        let key = key::get_keypress();
-        let m = match key {
-            0x4b | 0x1e => Some(Move::Left),
-            0x4d | 0x20 => Some(Move::Right),
-            0x48 | 0x11 => Some(Move::Up),
-            0x50 | 0x1f => Some(Move::Down),
-            _ => None,
+        // (also W, A, S, D unless rebound)
+        let direction = action.and_then(Action::direction);
+        // with inverted controls the direction is that of the empty slot,
+        // so the tile moves the opposite way
+        let tile_move = match direction {
+            Some(m) if setup.invert => Some(m.opposite()),
+            m => m,
+        };
+        // decide which move to make and how many times
+        let slide = match (action, setup.controls) {
+            // take back the last move
+            (Some(Action::Undo), _) => history.last().map(|m| (m.opposite(), 1)),
+            // make the next move of a solution (not in hardcore mode)
+            (Some(Action::Hint), _) if setup.can_peek => {
+                hints.next(tiles, HINT_MAX_NODES).map(|m| (m, 1))
+            }
+            (_, Controls::Direct) => tile_move.map(|m| (m, 1)),
+            (_, Controls::Cursor) => {
+                if let Some(m) = direction {
+                    // move the cursor, putting back the tile it was over
+                    let (x, y) = cursor;
+                    draw_tile(tiles, picture, &layout, tile_at(tiles, x, y), setup.overlay);
+                    cursor = move_cursor(cursor, m, cols, rows);
+                    draw_cursor(&layout, cursor);
+                    // the tile may have covered the meter along the top edge
+                    if setup.overlay.highlight {
+                        draw_completion_meter(tiles);
+                    }
+                    None
+                } else if key == 0x1c {
+                    // Enter: slide the tiles from the cursor up to the empty slot
+                    tiles.slide_towards(cursor.0, cursor.1)
+                } else {
+                    None
+                }
+            }
         };
-        if let Some(m) = m {
+        let Some((m, count)) = slide else {
+            continue;
+        };
+        for _ in 0..count {
             if !tiles.is_valid_move(m) {
-                continue;
+                break;
             }
 
             // move the tile with an animation
-            animate_tile_move(
-                &tiles,
-                picture,
-                (width, height),
-                (tile_width, tile_height),
-                m,
-            );
+            animate_tile_move(tiles, picture, &layout, m, setup.animation);
             // apply the move proper
+            let (empty_x, empty_y) = (tiles.empty_x, tiles.empty_y);
             tiles.do_move(m);
-            // increment the move counter
-            add_move();
+            if setup.overlay != Overlay::default() {
+                // bring back the overlay of the tile moved
+                // (outlined if it reached its home position)
+                let moved = tile_at(tiles, empty_x, empty_y);
+                draw_tile(tiles, picture, &layout, moved, setup.overlay);
+            }
+            if setup.overlay.highlight {
+                draw_completion_meter(tiles);
+            }
+            // increment the move counters
+            // (undoing a move also counts as a move)
+            attempt.moves += 1;
+            stats.moves += 1;
+            match action {
+                Some(Action::Undo) => {
+                    history.pop();
+                    stats.undos += 1;
+                }
+                Some(Action::Hint) => {
+                    history.push(m);
+                    stats.hints += 1;
+                }
+                _ => history.push(m),
+            }
             // click!
             play_click();
 
             // check if the puzzle is solved
             if tiles.is_won() {
+                stats.solved = true;
+                slide_in_missing_tile(tiles, picture, &layout, setup.animation);
                 return LevelOutcome::NextLevel;
             }
 
-            // draw the updated tiles
-            unsafe {
-                vsync();
+            // update the moves remaining
+            if let Some(budget) = move_budget {
+                let remaining = budget.saturating_sub(attempt.moves);
+                draw_countdown_bar(remaining, budget, &layout);
+                if remaining == 0 {
+                    return LevelOutcome::Failed;
+                }
             }
         }
+        if setup.controls == Controls::Cursor {
+            draw_cursor(&layout, cursor);
+        }
+    }
+}
 
-        unsafe {
-            vsync();
+/// The number of the tile at the given grid position
+fn tile_at(tiles: &Tiles, x: u8, y: u8) -> u16 {
+    tiles.tiles[y as usize * tiles.cols as usize + x as usize] as u16
+}
 
-            // implement exit (ESC)
-            let key = key::get_keypress();
-            if key == 0x01 {
-                return LevelOutcome::Exit;
-            }
-        }
+/// Move the cursor one grid position in the direction given,
+/// staying within the grid.
+fn move_cursor((x, y): (u8, u8), m: Move, cols: u8, rows: u8) -> (u8, u8) {
+    match m {
+        Move::Left => (x.saturating_sub(1), y),
+        Move::Right => ((x + 1).min(cols - 1), y),
+        Move::Up => (x, y.saturating_sub(1)),
+        Move::Down => (x, (y + 1).min(rows - 1)),
+    }
+}
+
+/// Draw the cursor as a thick frame over the grid position given.
+fn draw_cursor(layout: &Layout, (x, y): (u8, u8)) {
+    let (x, y) = layout.pixel_position_i32(x, y);
+    let (width, height) = (layout.tile_width, layout.tile_height);
+    let thickness = CURSOR_THICKNESS;
+    screen::draw_rect(x, y, width, thickness, CURSOR_COLOR);
+    screen::draw_rect(x, y, thickness, height, CURSOR_COLOR);
+    screen::draw_rect(
+        x,
+        y + (height - thickness) as i32,
+        width,
+        thickness,
+        CURSOR_COLOR,
+    );
+    screen::draw_rect(
+        x + (width - thickness) as i32,
+        y,
+        thickness,
+        height,
+        CURSOR_COLOR,
+    );
+}
+
+/// Compose the text of the HUD:
+/// level, moves in this level (and in the whole session,
+/// or the moves left in move limit mode),
+/// time spent in this level and tiles in their place.
+fn hud_text_for(
+    tiles: &Tiles,
+    setup: &LevelSetup,
+    stats: &LevelStats,
+    moves_left: Option<u32>,
+) -> String {
+    let seconds = timer::ticks_to_seconds(stats.ticks);
+    let moves = match moves_left {
+        Some(left) => format!("{} left", left),
+        None => format!("{}", setup.previous_moves + stats.moves),
+    };
+    format!(
+        "L{}  Moves {} ({})  {}:{:02}  {}/{}",
+        setup.level + 1,
+        stats.moves,
+        moves,
+        seconds / 60,
+        seconds % 60,
+        tiles.count_in_place(),
+        tiles.cols as u32 * tiles.rows as u32 - 1,
+    )
+}
+
+/// Draw the HUD strip below the puzzle area.
+///
+/// The countdown bar, if any, sits at the top of the strip.
+fn draw_hud(text: &str, layout: &Layout) {
+    let y = (layout.height + TIMER_BAR_HEIGHT) as i32;
+    screen::draw_rect(0, y, 320, 200 - y as u32, 255);
+    font::draw_text_plain(4, y + 1, text, 0xFE);
+}
+
+/// Draw the completion meter at the top of the screen,
+/// filled in proportion to the tiles in their home position.
+fn draw_completion_meter(tiles: &Tiles) {
+    let total = tiles.cols as u32 * tiles.rows as u32 - 1;
+    let filled = 320 * tiles.count_in_place() / total;
+    screen::draw_rect(0, 0, 320, METER_HEIGHT, 255);
+    if filled > 0 {
+        screen::draw_rect(0, 0, filled, METER_HEIGHT, HIGHLIGHT_COLOR);
+    }
+}
+
+/// Draw the countdown bar below the puzzle,
+/// filled in proportion to the time remaining.
+fn draw_countdown_bar(remaining: u32, budget: u32, layout: &Layout) {
+    let filled = (320 * remaining / budget.max(1)).min(320);
+    let y = layout.height as i32;
+    screen::draw_rect(0, y, 320, TIMER_BAR_HEIGHT, 255);
+    if filled > 0 {
+        screen::draw_rect(0, y, filled, TIMER_BAR_HEIGHT, TIMER_BAR_COLOR);
     }
 }
 
 fn animate_tile_move(
     tiles: &Tiles,
     picture: &[u8],
-    (width, height): (u32, u32),
-    (tile_width, tile_height): (u32, u32),
+    layout: &Layout,
     m: Move,
+    (slide_speed, easing): (SlideSpeed, Easing),
 ) {
+    let (tile_width, tile_height) = (layout.tile_width, layout.tile_height);
+
     // get the x,y coordinates of the tile being moved
     // (plus the direction of movement)
     let (x, y, delta_x, delta_y) = match m {
@@ -425,103 +1670,107 @@ fn animate_tile_move(
     // determine the position of the tile to move in the picture
     let (pic_x, pic_y) = tiles.position_of(x, y);
 
-    let (origin_x, origin_y) = pixel_position(
-        pic_x,
-        pic_y,
-        tile_width,
-        tile_height,
-        tiles.cols,
-        tiles.rows,
-    );
-    let origin = (origin_x, origin_y, tile_width, tile_height);
-
-    let (x, y) = pixel_position_i32(x, y, tile_width, tile_height, tiles.cols, tiles.rows);
+    let origin = layout.pixel_position(pic_x, pic_y);
 
-    let mut d_x = 0;
-    let mut d_y = 0;
+    let (x, y) = layout.pixel_position_i32(x, y);
 
     let amount = match m {
         Move::Up | Move::Down => tile_height,
         Move::Left | Move::Right => tile_width,
-    };
-
-    for _ in (1..=amount).step_by(2) {
-        d_x += delta_x + delta_x;
-        d_y += delta_y + delta_y;
-        let target = (x + d_x, y + d_y);
-
-        unsafe {
-            vsync();
-            dos_x::vga::blit_rect(picture, (width as u32, height as u32), origin, target);
-        }
-        // clear out the trailing space
-        match m {
-            Move::Up => unsafe {
-                dos_x::vga::draw_hline(x, y + d_y as i32 + tile_height as i32, tile_width, 255);
-                dos_x::vga::draw_hline(x, y + d_y as i32 + tile_height as i32 + 1, tile_width, 255);
-            },
-            Move::Down => unsafe {
-                dos_x::vga::draw_hline(x, y + d_y as i32 - 1, tile_width, 255);
-                dos_x::vga::draw_hline(x, y + d_y as i32 - 2, tile_width, 255);
-            },
-            Move::Left => unsafe {
-                dos_x::vga::draw_vline(x + d_x as i32 + tile_width as i32, y, tile_height, 255);
-                dos_x::vga::draw_vline(x + d_x as i32 + tile_width as i32 + 1, y, tile_height, 255);
-            },
-            Move::Right => unsafe {
-                dos_x::vga::draw_vline(x + d_x as i32 - 1, y, tile_height, 255);
-                dos_x::vga::draw_vline(x + d_x as i32 - 2, y, tile_height, 255);
-            },
+    } as i32;
+
+    // the slide takes the same time regardless of the size of the tile,
+    // following the easing curve chosen
+    let (mut last_x, mut last_y) = (x, y);
+    animate(slide_speed.millis(), |t| {
+        let offset = easing.apply(t) as i32 * amount / PROGRESS_MAX as i32;
+        let target = (x + delta_x * offset, y + delta_y * offset);
+
+        if target != (last_x, last_y) {
+            // clear out where the tile was
+            // (the back buffer keeps this from flickering)
+            screen::draw_rect(last_x, last_y, tile_width, tile_height, 255);
+            layout.draw_tile_at(picture, origin, target);
+            (last_x, last_y) = target;
         }
-        unsafe {
-            delay(5);
-        }
-    }
+        screen::present();
+    });
 }
 
-/// Draw all tiles of the puzzle iteratively, tile by tile.
-///
-/// Needed when the level starts.
-fn draw_tiles_animated(
+/// Slide the missing tile into the empty slot
+/// from the left edge of the screen, completing the picture.
+fn slide_in_missing_tile(
     tiles: &Tiles,
     picture: &[u8],
-    (width, height): (u32, u32),
-    (tile_width, tile_height): (u32, u32),
+    layout: &Layout,
+    (slide_speed, easing): (SlideSpeed, Easing),
 ) {
+    let (tile_width, tile_height) = (layout.tile_width, layout.tile_height);
+    let (x, y) = layout.pixel_position_i32(tiles.empty_x, tiles.empty_y);
+    let origin = (x as u32, y as u32);
+    let start_x = -(tile_width as i32);
+    let distance = x - start_x;
+
+    let mut last_x = start_x;
+    animate(slide_speed.millis() * 2, |t| {
+        let target_x = start_x + easing.apply(t) as i32 * distance / PROGRESS_MAX as i32;
+        screen::draw_rect(last_x, y, tile_width, tile_height, 255);
+        layout.draw_tile_at(picture, origin, (target_x, y));
+        last_x = target_x;
+        screen::present();
+    });
+}
+
+/// Draw a single tile of the puzzle at its current grid position
+/// (or a black rectangle for the empty slot).
+fn draw_tile(tiles: &Tiles, picture: &[u8], layout: &Layout, k: u16, overlay: Overlay) {
     let cols = tiles.cols as u16;
+    let (i, j) = tiles.where_is(k);
+    let (x, y) = layout.pixel_position_i32(i, j);
 
-    // draw puzzle tiles to the screen
-    for k in 0..cols as u16 * tiles.rows as u16 {
-        let (i, j) = tiles.where_is(k);
-        let (x, y) = pixel_position_i32(i, j, tile_width, tile_height, tiles.cols, tiles.rows);
+    if k == 0 {
+        screen::draw_rect(x, y, layout.tile_width, layout.tile_height, 255);
+        return;
+    }
 
-        if k == 0 {
-            // draw a black rectangle instead
-            unsafe {
-                vsync();
-                dos_x::vga::draw_rect(x, y, width as u32, height as u32, 255);
-            }
+    let tile_n = (k % cols) as u8;
+    let tile_m = (k / cols) as u8;
+    layout.draw_tile_at(picture, layout.pixel_position(tile_n, tile_m), (x, y));
 
-            continue;
-        }
-        let target = (x, y);
+    if overlay.numbers {
+        font::draw_text(x + 3, y + 3, &format!("{}", k), 0xFE);
+    }
 
-        let tile_n = (k % cols) as u8;
-        let tile_m = (k / cols) as u8;
+    // outline the tile if it is in its home position
+    if overlay.highlight && tiles.position_of(i, j) == (i, j) {
+        let (width, height) = (layout.tile_width, layout.tile_height);
+        screen::draw_hline(x, y, width, HIGHLIGHT_COLOR);
+        screen::draw_hline(x, y + height as i32 - 1, width, HIGHLIGHT_COLOR);
+        screen::draw_vline(x, y, height, HIGHLIGHT_COLOR);
+        screen::draw_vline(x + width as i32 - 1, y, height, HIGHLIGHT_COLOR);
+    }
+}
 
-        let (origin_x, origin_y) = pixel_position(
-            tile_n,
-            tile_m,
-            tile_width,
-            tile_height,
-            tiles.cols,
-            tiles.rows,
-        );
-        let origin = (origin_x, origin_y, tile_width as u32, tile_height as u32);
-        unsafe {
-            vsync();
-            dos_x::vga::blit_rect(picture, (width as u32, height as u32), origin, target);
+/// Draw all tiles of the puzzle at once.
+///
+/// Needed when the layout of the screen changes.
+fn draw_tiles(tiles: &Tiles, picture: &[u8], layout: &Layout, overlay: Overlay) {
+    for k in 0..tiles.cols as u16 * tiles.rows as u16 {
+        draw_tile(tiles, picture, layout, k, overlay);
+    }
+}
 
+/// Draw all tiles of the puzzle iteratively, tile by tile.
+///
+/// Needed when the level starts.
+fn draw_tiles_animated(tiles: &Tiles, picture: &[u8], layout: &Layout, overlay: Overlay) {
+    for k in 0..tiles.cols as u16 * tiles.rows as u16 {
+        draw_tile(tiles, picture, layout, k, overlay);
+        screen::present();
+        if k == 0 {
+            continue;
+        }
+        unsafe {
             // timeout after drawing each tile for an animation effect
             // (making it faster if the puzzle is larger)
             delay(if tiles.cols > 4 { 50 } else { 75 });
@@ -529,50 +1778,11 @@ fn draw_tiles_animated(
     }
 }
 
-/// Obtain the expected x,y coordinates in pixels of a grid position,
-#[inline]
-fn pixel_position(
-    col: u8,
-    row: u8,
-    tile_width: u32,
-    tile_height: u32,
-    cols: u8,
-    rows: u8,
-) -> (u32, u32) {
-    let mut x = col as u32 * tile_width;
-    let mut y = row as u32 * tile_height;
-
-    // compensate with +1px in the case of 3 columns
-    // so that the puzzle stays centered horizontally
-    if cols == 3 {
-        x += 1;
-    }
-
-    // compensate with +1px in the case of 3 rows
-    // so that the puzzle stays centered vertically
-    if rows == 3 {
-        y += 1;
-    }
-
-    (x, y)
-}
-
-/// Obtain the expected x,y coordinates in pixels of a grid position
-#[inline]
-fn pixel_position_i32(
-    col: u8,
-    row: u8,
-    tile_width: u32,
-    tile_height: u32,
-    cols: u8,
-    rows: u8,
-) -> (i32, i32) {
-    let (x, y) = pixel_position(col, row, tile_width, tile_height, cols, rows);
-    (x as i32, y as i32)
-}
-
+#[cfg(not(test))]
 #[panic_handler]
 fn handle_panic(info: &PanicInfo) -> ! {
+    // give the keyboard back to the BIOS
+    keyboard::uninstall();
     unsafe {
         // reset video mode
         dos_x::vga::set_video_mode(0x02);
@@ -0,0 +1,643 @@
+//! Title screen and menus (in video mode 13h)
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::bindings::{self, key_name, ACTIONS, RESERVED_KEYS};
+use crate::highscore::HighScores;
+use crate::input::Controls;
+use crate::joystick::{self, Calibration};
+use crate::palette_fx::Cycle;
+use crate::profile::Profile;
+use crate::settings::Settings;
+use crate::timer::ticks_to_seconds;
+use crate::{audio, font, grid_size, input, level_picture_id, screen, TIMER_BAR_COLOR};
+
+/// Color of regular text
+const TEXT_COLOR: u8 = 0xFE;
+
+/// Color of the item currently selected
+/// (same as the countdown bar)
+const SELECTED_COLOR: u8 = TIMER_BAR_COLOR;
+
+/// Palette color reserved for items which cannot be chosen
+const DISABLED_COLOR: u8 = 0xFC;
+
+/// First of the palette colors cycled in the title
+const TITLE_COLOR: u8 = 0xF8;
+
+/// The colors cycled in the title
+const TITLE_COLORS: [[u8; 3]; 4] = [[63, 63, 63], [63, 56, 32], [63, 48, 0], [63, 56, 32]];
+
+/// Time between rotations of the title colors in milliseconds
+const TITLE_CYCLE_MILLIS: u32 = 150;
+
+/// Vertical distance between menu items in pixels
+const ITEM_SPACING: i32 = 14;
+
+/// Number of levels per row in the level selection screen
+const LEVEL_SELECT_COLUMNS: u8 = 8;
+
+/// An option of the main menu
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MenuChoice {
+    NewGame,
+    Continue,
+    LevelSelect,
+    Options,
+    HighScores,
+    Quit,
+}
+
+/// An item in a menu
+struct MenuItem<'a> {
+    label: &'a str,
+    /// whether the item can be chosen
+    enabled: bool,
+}
+
+/// Ensure that the colors used by the menus are in the palette.
+fn set_menu_palette() {
+    unsafe {
+        dos_x::vga::set_color_single(0xFE, 63, 63, 63);
+        dos_x::vga::set_color_single(0xFF, 0, 0, 0);
+        dos_x::vga::set_color_single(SELECTED_COLOR, 63, 48, 0);
+        dos_x::vga::set_color_single(DISABLED_COLOR, 24, 24, 24);
+    }
+}
+
+/// Clear the screen to black.
+fn clear_screen() {
+    screen::draw_rect(0, 0, 320, 200, 255);
+}
+
+/// Block until a key is pressed, returning its scan code.
+///
+/// The colors of the title keep cycling in the meantime.
+fn wait_key() -> u8 {
+    let mut cycle = Cycle::new(TITLE_COLOR, &TITLE_COLORS, TITLE_CYCLE_MILLIS);
+    loop {
+        if let Some(key) = input::poll_key() {
+            return key;
+        }
+        cycle.update();
+    }
+}
+
+/// Draw the title of the game,
+/// each letter in one of the cycling colors.
+fn draw_title(y: i32) {
+    const TITLE: &str = "T I L E R S";
+    let x = (320 - font::text_width(TITLE) as i32) / 2;
+    for (i, c) in TITLE.char_indices() {
+        let color = TITLE_COLOR + (i / 2 % TITLE_COLORS.len()) as u8;
+        font::draw_text(
+            x + i as i32 * font::GLYPH_SIZE as i32,
+            y,
+            &TITLE[i..i + c.len_utf8()],
+            color,
+        );
+    }
+}
+
+/// Draw a vertical menu starting at the given y coordinate,
+/// one item every `ITEM_SPACING` pixels.
+fn draw_menu(y: i32, items: &[MenuItem], selected: usize) {
+    for (i, item) in items.iter().enumerate() {
+        let color = if i == selected {
+            SELECTED_COLOR
+        } else if item.enabled {
+            TEXT_COLOR
+        } else {
+            DISABLED_COLOR
+        };
+        font::draw_text_centered(y + i as i32 * ITEM_SPACING, item.label, color);
+    }
+}
+
+/// Let the player move through a menu with the arrow keys
+/// and pick an item with Enter.
+///
+/// `redraw` is called to draw everything else on the screen.
+/// Returns the index of the item chosen,
+/// or `None` if the player pressed ESC.
+fn run_menu(
+    y: i32,
+    items: &[MenuItem],
+    selected: &mut usize,
+    mut redraw: impl FnMut(),
+) -> Option<usize> {
+    loop {
+        clear_screen();
+        redraw();
+        draw_menu(y, items, *selected);
+        screen::present();
+
+        match wait_key() {
+            // ESC
+            0x01 => return None,
+            // Enter
+            0x1c if items[*selected].enabled => return Some(*selected),
+            // Up (skip disabled items)
+            0x48 => {
+                if let Some(i) = (0..*selected).rev().find(|&i| items[i].enabled) {
+                    *selected = i;
+                }
+            }
+            // Down (skip disabled items)
+            0x50 => {
+                if let Some(i) = (*selected + 1..items.len()).find(|&i| items[i].enabled) {
+                    *selected = i;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Show the title screen and main menu.
+pub fn title_screen(can_continue: bool, can_select_level: bool) -> MenuChoice {
+    const CHOICES: [MenuChoice; 6] = [
+        MenuChoice::NewGame,
+        MenuChoice::Continue,
+        MenuChoice::LevelSelect,
+        MenuChoice::Options,
+        MenuChoice::HighScores,
+        MenuChoice::Quit,
+    ];
+    let items = [
+        MenuItem {
+            label: "New game",
+            enabled: true,
+        },
+        MenuItem {
+            label: "Continue",
+            enabled: can_continue,
+        },
+        MenuItem {
+            label: "Level select",
+            enabled: can_select_level,
+        },
+        MenuItem {
+            label: "Options",
+            enabled: true,
+        },
+        MenuItem {
+            label: "High scores",
+            enabled: true,
+        },
+        MenuItem {
+            label: "Quit",
+            enabled: true,
+        },
+    ];
+
+    set_menu_palette();
+    // start at "Continue" if there is a game to continue
+    let mut selected = if can_continue { 1 } else { 0 };
+    let choice = run_menu(80, &items, &mut selected, || {
+        draw_title(24);
+        font::draw_text_centered(40, "A relaxing tile permutation puzzle", TEXT_COLOR);
+        font::draw_text_centered(184, "E_net4 (2024)", DISABLED_COLOR);
+    });
+    match choice {
+        Some(i) => CHOICES[i],
+        None => MenuChoice::Quit,
+    }
+}
+
+/// Show the options menu, changing the settings in place.
+///
+/// The options are split into pages,
+/// each one picked from this menu.
+pub fn options(settings: &mut Settings, profile: &Profile) {
+    set_menu_palette();
+    let items = [
+        MenuItem {
+            label: "Game",
+            enabled: true,
+        },
+        MenuItem {
+            label: "Controls",
+            enabled: true,
+        },
+        MenuItem {
+            label: "Display",
+            enabled: true,
+        },
+        MenuItem {
+            label: "Back",
+            enabled: true,
+        },
+    ];
+    let mut selected = 0;
+    loop {
+        let choice = run_menu(40, &items, &mut selected, || {
+            font::draw_text_centered(16, "Options", TEXT_COLOR);
+        });
+        match choice {
+            Some(0) => game_options(settings, profile),
+            Some(1) => control_options(settings),
+            Some(2) => display_options(settings),
+            _ => return,
+        }
+    }
+}
+
+/// Show the options about the rules of the game.
+///
+/// The secret difficulty can only be changed
+/// once unlocked in the profile.
+fn game_options(settings: &mut Settings, profile: &Profile) {
+    let mut selected = 0;
+    loop {
+        let sound = format!("Sound: {}", on_off(settings.sound));
+        let mode = format!("Mode: {}", settings.mode.name());
+        let secret = format!("Secret difficulty: {}", on_off(settings.secret));
+        let hardcore = format!("Hardcore: {}", on_off(settings.hardcore));
+        let items = [
+            MenuItem {
+                label: &sound,
+                enabled: true,
+            },
+            MenuItem {
+                label: &mode,
+                enabled: true,
+            },
+            MenuItem {
+                label: &secret,
+                enabled: profile.is_secret_unlocked(),
+            },
+            MenuItem {
+                label: &hardcore,
+                enabled: true,
+            },
+            MenuItem {
+                label: "Back",
+                enabled: true,
+            },
+        ];
+        let choice = run_menu(40, &items, &mut selected, || {
+            font::draw_text_centered(16, "Game options", TEXT_COLOR);
+        });
+        match choice {
+            Some(0) => {
+                settings.sound = !settings.sound;
+                audio::set_sound(settings.sound);
+            }
+            Some(1) => settings.mode = settings.mode.next(),
+            Some(2) => settings.secret = !settings.secret,
+            Some(3) => settings.hardcore = !settings.hardcore,
+            _ => return,
+        }
+    }
+}
+
+/// Show the options about the keyboard and the joystick.
+fn control_options(settings: &mut Settings) {
+    let mut selected = 0;
+    loop {
+        let controls = format!("Controls: {}", settings.controls.name());
+        let invert = format!("Invert controls: {}", on_off(settings.invert));
+        let joystick = format!("Joystick: {}", on_off(settings.joystick.is_some()));
+        let items = [
+            MenuItem {
+                label: &controls,
+                enabled: true,
+            },
+            MenuItem {
+                label: &invert,
+                enabled: settings.controls == Controls::Direct,
+            },
+            MenuItem {
+                label: &joystick,
+                enabled: settings.joystick.is_some() || joystick::is_present(),
+            },
+            MenuItem {
+                label: "Key bindings",
+                enabled: true,
+            },
+            MenuItem {
+                label: "Back",
+                enabled: true,
+            },
+        ];
+        let choice = run_menu(40, &items, &mut selected, || {
+            font::draw_text_centered(16, "Controls", TEXT_COLOR);
+        });
+        match choice {
+            Some(0) => settings.controls = settings.controls.next(),
+            Some(1) => settings.invert = !settings.invert,
+            Some(2) => {
+                // turning the joystick on calibrates it first
+                settings.joystick = match settings.joystick {
+                    Some(_) => None,
+                    None => calibrate_joystick(),
+                };
+            }
+            Some(3) => key_bindings(),
+            _ => return,
+        }
+    }
+}
+
+/// Show the options about how the game looks.
+fn display_options(settings: &mut Settings) {
+    let mut selected = 0;
+    loop {
+        let hud = format!("HUD: {}", on_off(settings.hud));
+        let highlight = format!("Highlight tiles: {}", on_off(settings.highlight));
+        let tile_style = format!("Tiles: {}", settings.tile_style.name());
+        let border = format!("Borders: {}", settings.border.name());
+        let slide_speed = format!("Slide speed: {}", settings.slide_speed.name());
+        let easing = format!("Easing: {}", settings.easing.name());
+        let transition = format!("Transition: {}", settings.transition.name());
+        let items = [
+            MenuItem {
+                label: &hud,
+                enabled: true,
+            },
+            MenuItem {
+                label: &highlight,
+                enabled: true,
+            },
+            MenuItem {
+                label: &tile_style,
+                enabled: true,
+            },
+            MenuItem {
+                label: &border,
+                enabled: true,
+            },
+            MenuItem {
+                label: &slide_speed,
+                enabled: true,
+            },
+            MenuItem {
+                label: &easing,
+                enabled: true,
+            },
+            MenuItem {
+                label: &transition,
+                enabled: true,
+            },
+            MenuItem {
+                label: "Back",
+                enabled: true,
+            },
+        ];
+        let choice = run_menu(40, &items, &mut selected, || {
+            font::draw_text_centered(16, "Display", TEXT_COLOR);
+        });
+        match choice {
+            Some(0) => settings.hud = !settings.hud,
+            Some(1) => settings.highlight = !settings.highlight,
+            Some(2) => settings.tile_style = settings.tile_style.next(),
+            Some(3) => settings.border = settings.border.next(),
+            Some(4) => settings.slide_speed = settings.slide_speed.next(),
+            Some(5) => settings.easing = settings.easing.next(),
+            Some(6) => settings.transition = settings.transition.next(),
+            _ => return,
+        }
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+/// Show the key bindings and let the player change them,
+/// saving them to the configuration file when leaving.
+///
+/// Up and Down pick an action, Left and Right pick one of its two keys,
+/// Enter asks for the new key and Delete removes it.
+fn key_bindings() {
+    let mut bindings = bindings::current();
+    let original = bindings;
+    // the rows are the actions followed by the keyboard layout
+    let mut row = 0;
+    let mut slot = 0;
+    loop {
+        clear_screen();
+        font::draw_text_centered(12, "Key bindings", TEXT_COLOR);
+        for (i, action) in ACTIONS.into_iter().enumerate() {
+            let y = 32 + i as i32 * 12;
+            font::draw_text(40, y, action.name(), TEXT_COLOR);
+            for (s, &key) in bindings.keys(action).iter().enumerate() {
+                let name = key_name(key, bindings.layout).unwrap_or("---");
+                let color = if (row, slot) == (i, s) {
+                    SELECTED_COLOR
+                } else {
+                    TEXT_COLOR
+                };
+                font::draw_text(128 + s as i32 * 96, y, name, color);
+            }
+        }
+        let layout = format!("Layout: {}", bindings.layout.name());
+        let color = if row == ACTIONS.len() {
+            SELECTED_COLOR
+        } else {
+            TEXT_COLOR
+        };
+        font::draw_text_centered(148, &layout, color);
+        font::draw_text_centered(172, "Enter: change, Del: clear", DISABLED_COLOR);
+        font::draw_text_centered(184, "ESC: save and back", DISABLED_COLOR);
+        screen::present();
+
+        match wait_key() {
+            // ESC
+            0x01 => break,
+            // Enter
+            0x1c if row == ACTIONS.len() => bindings.layout = bindings.layout.next(),
+            0x1c => {
+                let action = ACTIONS[row];
+                let message = format!("Press a key for {}", action.name());
+                font::draw_banner(96, &message, TEXT_COLOR);
+                screen::present();
+                let key = wait_key();
+                // ESC cancels, and keys without a name cannot be written down
+                if key != 0x01
+                    && !RESERVED_KEYS.contains(&key)
+                    && key_name(key, bindings.layout).is_some()
+                    && !bindings.bind(action, slot, key)
+                {
+                    show_quit_needs_key();
+                }
+            }
+            // Delete
+            0x53 if row < ACTIONS.len() && !bindings.unbind(ACTIONS[row], slot) => {
+                show_quit_needs_key()
+            }
+            0x48 => row = row.saturating_sub(1),
+            0x50 => row = (row + 1).min(ACTIONS.len()),
+            0x4b => slot = 0,
+            0x4d => slot = 1,
+            _ => {}
+        }
+    }
+
+    if bindings != original {
+        bindings::set_current(bindings);
+        let _ = bindings.save();
+    }
+}
+
+/// Tell the player that the key asked for
+/// would leave no way to quit a level.
+fn show_quit_needs_key() {
+    font::draw_banner(96, "Quit needs at least one key", TEXT_COLOR);
+    screen::present();
+    wait_key();
+}
+
+/// Walk the player through calibrating the joystick.
+///
+/// Returns `None` if the player gave up with ESC,
+/// the joystick went away
+/// or the positions measured make no sense.
+fn calibrate_joystick() -> Option<Calibration> {
+    const STEPS: [&str; 3] = [
+        "Leave the stick centered",
+        "Push the stick to the top left",
+        "Push the stick to the bottom right",
+    ];
+    let mut positions = [[0; 2]; 3];
+    for (step, position) in STEPS.iter().zip(&mut positions) {
+        *position = wait_joystick_button(step)?;
+    }
+    let calibration = Calibration {
+        center: positions[0],
+        min: positions[1],
+        max: positions[2],
+    };
+    if calibration.is_valid() {
+        return Some(calibration);
+    }
+
+    clear_screen();
+    font::draw_text_centered(16, "Joystick calibration", TEXT_COLOR);
+    font::draw_text_centered(96, "Calibration failed, try again", TEXT_COLOR);
+    font::draw_text_centered(184, "Press any key", DISABLED_COLOR);
+    screen::present();
+    wait_key();
+    None
+}
+
+/// Show a calibration instruction along with the position of the stick
+/// until a joystick button is pressed and released,
+/// returning the position at the time of the press.
+fn wait_joystick_button(instruction: &str) -> Option<[u16; 2]> {
+    loop {
+        // ESC
+        if input::poll_key() == Some(0x01) {
+            return None;
+        }
+        let reading = joystick::read()?;
+
+        clear_screen();
+        font::draw_text_centered(16, "Joystick calibration", TEXT_COLOR);
+        font::draw_text_centered(80, instruction, TEXT_COLOR);
+        font::draw_text_centered(96, "and press a button", TEXT_COLOR);
+        let position = format!("X {:>5}  Y {:>5}", reading.axes[0], reading.axes[1]);
+        font::draw_text_centered(128, &position, DISABLED_COLOR);
+        font::draw_text_centered(184, "ESC: cancel", DISABLED_COLOR);
+        screen::present();
+
+        if reading.buttons != 0 {
+            while joystick::read()?.buttons != 0 {}
+            return Some(reading.axes);
+        }
+    }
+}
+
+/// Show the best scores of each level, until a key is pressed.
+pub fn high_scores(high_scores: &HighScores, level_count: u8) {
+    set_menu_palette();
+    clear_screen();
+    font::draw_text_centered(16, "High scores", TEXT_COLOR);
+
+    let lines: Vec<String> = (0..level_count.min(16))
+        .map(|level| {
+            // on the usual grid size of the level
+            let grid = grid_size(level, false);
+            let best = level_picture_id(level).and_then(|id| high_scores.get(&id, grid));
+            match best {
+                Some(best) => {
+                    let seconds = ticks_to_seconds(best.ticks);
+                    format!(
+                        "Level {:>2}: {:>4} moves {:>3}:{:02}",
+                        level + 1,
+                        best.moves,
+                        seconds / 60,
+                        seconds % 60
+                    )
+                }
+                None => format!("Level {:>2}:       ---       ", level + 1),
+            }
+        })
+        .collect();
+    for (i, line) in lines.iter().enumerate() {
+        font::draw_text(40, 40 + i as i32 * 8, line, TEXT_COLOR);
+    }
+    font::draw_text_centered(184, "Press any key", DISABLED_COLOR);
+    screen::present();
+    wait_key();
+}
+
+/// Show the level selection screen.
+///
+/// The secret difficulty can be toggled with Tab
+/// if it was unlocked.
+/// Returns the level chosen,
+/// or `None` if the player wants to go back.
+pub fn select_level(profile: &Profile, level_count: u8, settings: &mut Settings) -> Option<u8> {
+    set_menu_palette();
+
+    // start at the furthest level unlocked
+    let mut selected = (0..level_count)
+        .rev()
+        .find(|&l| profile.is_unlocked(l))
+        .unwrap_or(0);
+
+    loop {
+        clear_screen();
+        font::draw_text_centered(16, "Select a level", TEXT_COLOR);
+        for l in 0..level_count {
+            let x = (l % LEVEL_SELECT_COLUMNS) as i32 * 40;
+            let y = 40 + (l / LEVEL_SELECT_COLUMNS) as i32 * 9;
+            let color = if l == selected {
+                SELECTED_COLOR
+            } else if profile.is_unlocked(l) {
+                TEXT_COLOR
+            } else {
+                DISABLED_COLOR
+            };
+            let mark = if profile.is_completed(l) { "*" } else { "" };
+            font::draw_text(x, y, &format!("{:>3}{}", l + 1, mark), color);
+        }
+        if profile.is_secret_unlocked() {
+            let message = format!("Secret difficulty: {} (Tab)", on_off(settings.secret));
+            font::draw_text_centered(164, &message, TEXT_COLOR);
+        }
+        font::draw_text_centered(180, "Arrows, Enter: play, ESC: back", TEXT_COLOR);
+        screen::present();
+
+        match wait_key() {
+            // ESC
+            0x01 => return None,
+            // Enter
+            0x1c if profile.is_unlocked(selected) => return Some(selected),
+            // Tab
+            0x0f if profile.is_secret_unlocked() => settings.secret = !settings.secret,
+            0x4b => selected = selected.saturating_sub(1),
+            0x4d => selected = (selected + 1).min(level_count - 1),
+            0x48 => selected = selected.saturating_sub(LEVEL_SELECT_COLUMNS),
+            0x50 => selected = (selected + LEVEL_SELECT_COLUMNS).min(level_count - 1),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! The look of the classic 15-puzzle:
+//! numbered tiles instead of a picture
+use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
+use dos_x::vga::Palette;
+
+use crate::border::Border;
+use crate::highscore::PictureId;
+use crate::{font, screen, Layout};
+
+/// Color behind the tiles
+const BACKGROUND_COLOR: u8 = 0;
+/// Color of the face of the tiles
+const FACE_COLOR: u8 = 1;
+/// Color of the top and left edges of the tiles
+const LIGHT_COLOR: u8 = 2;
+/// Color of the bottom and right edges of the tiles
+const DARK_COLOR: u8 = 3;
+/// Color of the digits
+const DIGIT_COLOR: u8 = 4;
+
+/// Width of the bevelled edges in pixels
+const BEVEL: u32 = 2;
+
+/// What the tiles show
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum TileStyle {
+    /// pieces of the level picture
+    Picture,
+    /// pieces of the level picture, with their number in a corner
+    PictureNumbered,
+    /// bevelled squares with digits
+    Numbers,
+}
+
+impl TileStyle {
+    /// The name of the tile style, as shown in the options
+    pub fn name(self) -> &'static str {
+        match self {
+            TileStyle::Picture => "Picture",
+            TileStyle::PictureNumbered => "Picture + numbers",
+            TileStyle::Numbers => "Numbers",
+        }
+    }
+
+    /// The tile style after this one in the options
+    pub fn next(self) -> Self {
+        match self {
+            TileStyle::Picture => TileStyle::PictureNumbered,
+            TileStyle::PictureNumbered => TileStyle::Numbers,
+            TileStyle::Numbers => TileStyle::Picture,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(TileStyle::Picture),
+            1 => Some(TileStyle::PictureNumbered),
+            2 => Some(TileStyle::Numbers),
+            _ => None,
+        }
+    }
+}
+
+/// Set up the colors of the numbered tiles in the palette
+/// (everything else black).
+pub fn set_palette(palette: &mut Palette) {
+    palette.0 = [0; 768];
+    for (color, rgb) in [
+        (BACKGROUND_COLOR, [8, 8, 12]),
+        (FACE_COLOR, [44, 36, 24]),
+        (LIGHT_COLOR, [58, 52, 40]),
+        (DARK_COLOR, [24, 18, 10]),
+        (DIGIT_COLOR, [12, 8, 4]),
+    ] {
+        let i = color as usize * 3;
+        palette.0[i..i + 3].copy_from_slice(&rgb);
+    }
+}
+
+/// The identifier of the numbered picture for high scores,
+/// which only depends on the size of the puzzle
+pub fn picture_id(cols: u8, rows: u8) -> PictureId {
+    md5::compute([b'N', b'U', b'M', cols, rows]).0
+}
+
+/// Render the solved puzzle as a full screen picture (320x200).
+pub fn render(cols: u8, rows: u8) -> Vec<u8> {
+    let layout = Layout::new(cols, rows, false, false, Border::None);
+    let (width, height) = (layout.tile_width, layout.tile_height);
+    // large tiles get larger digits
+    let scale = if height >= 32 { 2 } else { 1 };
+
+    let mut picture = vec![BACKGROUND_COLOR; 320 * 200];
+    for row in 0..rows {
+        for col in 0..cols {
+            let number = row as u32 * cols as u32 + col as u32;
+            // the home of the empty slot
+            if number == 0 {
+                continue;
+            }
+            let (x, y) = layout.pixel_position_i32(col, row);
+            screen::draw_rect_into(&mut picture, x, y, width, height, DARK_COLOR);
+            screen::draw_rect_into(
+                &mut picture,
+                x,
+                y,
+                width - BEVEL,
+                height - BEVEL,
+                LIGHT_COLOR,
+            );
+            screen::draw_rect_into(
+                &mut picture,
+                x + BEVEL as i32,
+                y + BEVEL as i32,
+                width - BEVEL * 2,
+                height - BEVEL * 2,
+                FACE_COLOR,
+            );
+
+            let text = format!("{}", number);
+            let text_width = font::text_width(&text) * scale;
+            let text_height = font::GLYPH_SIZE * scale;
+            font::draw_text_scaled_into(
+                &mut picture,
+                x + (width - text_width) as i32 / 2,
+                y + (height - text_height) as i32 / 2,
+                &text,
+                DIGIT_COLOR,
+                scale,
+            );
+        }
+    }
+    picture
+}
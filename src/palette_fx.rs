@@ -0,0 +1,103 @@
+//! Palette effects: fades, flashes and color cycling.
+//!
+//! All effects are timed in milliseconds,
+//! so they last the same regardless of the refresh rate.
+use alloc::vec::Vec;
+use dos_x::vga::{vsync, Palette};
+
+use crate::anim::{animate, PROGRESS_MAX};
+use crate::timer;
+
+/// Blend two palettes,
+/// from `from` at 0 to `to` at `PROGRESS_MAX`.
+fn blend(from: &[u8; 768], to: &[u8; 768], t: u32) -> Palette {
+    let mut out = [0; 768];
+    for (o, (&a, &b)) in out.iter_mut().zip(from.iter().zip(to)) {
+        *o = ((a as u32 * (PROGRESS_MAX - t) + b as u32 * t) / PROGRESS_MAX) as u8;
+    }
+    Palette::new(out)
+}
+
+/// Gradually change the palette from one to another.
+pub fn cross_fade(from: &Palette, to: &Palette, duration: u32) {
+    animate(duration, |t| {
+        let palette = blend(&from.0, &to.0, t);
+        unsafe {
+            vsync();
+        }
+        palette.set();
+    });
+}
+
+/// Fade from the given palette to black.
+pub fn fade_out(palette: &Palette, duration: u32) {
+    cross_fade(palette, &Palette::new([0; 768]), duration);
+}
+
+/// Fade from black to the given palette.
+pub fn fade_in(palette: &Palette, duration: u32) {
+    cross_fade(&Palette::new([0; 768]), palette, duration);
+}
+
+/// Set the palette for the given moment of a flash
+/// to white and back to the given palette,
+/// so that it can run alongside something else.
+///
+/// Past the duration of the flash, the palette is fully restored.
+pub fn flash_at(palette: &Palette, elapsed: u32, duration: u32) {
+    let white = [63; 768];
+    let half = (duration / 2).max(1);
+    let t = if elapsed < half {
+        elapsed * PROGRESS_MAX / half
+    } else {
+        PROGRESS_MAX - ((elapsed - half) * PROGRESS_MAX / half).min(PROGRESS_MAX)
+    };
+    blend(&palette.0, &white, t).set();
+}
+
+/// A range of palette colors rotating at a steady pace
+pub struct Cycle {
+    /// the first palette index of the range
+    first: u8,
+    /// the colors of the range, in their current order
+    colors: Vec<[u8; 3]>,
+    /// time between rotations in milliseconds
+    period: u32,
+    /// when the colors last rotated
+    last: u32,
+}
+
+impl Cycle {
+    /// Set up the colors of the range in the palette
+    /// and start cycling them.
+    pub fn new(first: u8, colors: &[[u8; 3]], period: u32) -> Self {
+        let cycle = Cycle {
+            first,
+            colors: colors.to_vec(),
+            period,
+            last: timer::millis(),
+        };
+        cycle.set();
+        cycle
+    }
+
+    fn set(&self) {
+        for (i, &[r, g, b]) in self.colors.iter().enumerate() {
+            unsafe {
+                dos_x::vga::set_color_single(self.first + i as u8, r, g, b);
+            }
+        }
+    }
+
+    /// Rotate the colors by one
+    /// if enough time passed since the last rotation.
+    pub fn update(&mut self) {
+        let now = timer::millis();
+        if now.wrapping_sub(self.last) < self.period {
+            return;
+        }
+        self.last = now;
+        self.colors.rotate_left(1);
+        self.set();
+    }
+}
@@ -0,0 +1,111 @@
+//! Player progress persisted across sessions
+//! (the settings are kept in the configuration file instead)
+use core::ffi::CStr;
+
+use crate::storage;
+
+/// The file which holds the player profile
+const FILENAME: &CStr = c"TILERS.PRF";
+
+/// Magic bytes of the profile file
+const MAGIC: &[u8; 4] = b"TLPF";
+
+/// Version of the profile file format
+///
+/// Earlier versions start with the same progress,
+/// followed by settings which are now ignored.
+const VERSION: u8 = 9;
+
+/// Length of the progress at the start of every version of the profile
+const PROGRESS_LEN: usize = 17;
+
+/// Flag for the secret difficulty being unlocked
+const FLAG_SECRET_UNLOCKED: u8 = 0x01;
+
+/// The progress of the player
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct Profile {
+    /// bit set of the levels completed
+    completed: u128,
+    /// whether the secret difficulty was earned
+    secret_unlocked: bool,
+}
+
+impl Profile {
+    /// Load the profile from disk.
+    ///
+    /// A missing or corrupt file results in a fresh profile.
+    pub fn load() -> Self {
+        storage::read_any_record(FILENAME, MAGIC)
+            .and_then(|(version, payload)| Self::decode(version, &payload))
+            .unwrap_or_default()
+    }
+
+    fn decode(version: u8, payload: &[u8]) -> Option<Self> {
+        let valid = if version == VERSION {
+            payload.len() == PROGRESS_LEN
+        } else {
+            version < VERSION && payload.len() >= PROGRESS_LEN
+        };
+        if !valid {
+            return None;
+        }
+        let mut completed = [0; 16];
+        completed.copy_from_slice(&payload[..16]);
+        Some(Profile {
+            completed: u128::from_le_bytes(completed),
+            secret_unlocked: payload[16] & FLAG_SECRET_UNLOCKED != 0,
+        })
+    }
+
+    /// Save the profile to disk.
+    pub fn save(&self) -> Result<(), storage::Error> {
+        let mut payload = [0; PROGRESS_LEN];
+        payload[..16].copy_from_slice(&self.completed.to_le_bytes());
+        if self.secret_unlocked {
+            payload[16] |= FLAG_SECRET_UNLOCKED;
+        }
+        storage::write_record(FILENAME, MAGIC, VERSION, &payload)
+    }
+
+    /// Whether any level was ever completed
+    pub fn has_progress(&self) -> bool {
+        self.completed != 0
+    }
+
+    pub fn is_completed(&self, level: u8) -> bool {
+        level < 128 && self.completed & (1 << level) != 0
+    }
+
+    /// Whether the player can start playing from the given level:
+    /// the first level is always unlocked,
+    /// and completing a level unlocks the next one.
+    pub fn is_unlocked(&self, level: u8) -> bool {
+        level == 0 || self.is_completed(level) || self.is_completed(level - 1)
+    }
+
+    /// Record a level as completed.
+    ///
+    /// Returns whether this is the first time.
+    pub fn complete(&mut self, level: u8) -> bool {
+        if level >= 128 || self.is_completed(level) {
+            return false;
+        }
+        self.completed |= 1 << level;
+        true
+    }
+
+    /// Whether the secret difficulty was earned
+    pub fn is_secret_unlocked(&self) -> bool {
+        self.secret_unlocked
+    }
+
+    /// Earn the secret difficulty.
+    ///
+    /// Returns whether this is the first time.
+    pub fn unlock_secret(&mut self) -> bool {
+        let first = !self.secret_unlocked;
+        self.secret_unlocked = true;
+        first
+    }
+}
@@ -0,0 +1,180 @@
+//! Saving and resuming a game in progress
+use alloc::vec::Vec;
+use core::ffi::CStr;
+
+use crate::stats::{LevelStats, SessionStats};
+use crate::storage::{self, Reader};
+use crate::tiles::Tiles;
+use crate::{Attempt, GameMode};
+
+/// The file which holds the saved game
+const FILENAME: &CStr = c"TILERS.SAV";
+
+/// Magic bytes of the saved game file
+const MAGIC: &[u8; 4] = b"TLSV";
+
+/// Version of the saved game file format
+const VERSION: u8 = 4;
+
+/// Everything needed to continue a game where it was left off
+#[derive(Debug)]
+pub struct SavedGame {
+    pub mode: GameMode,
+    /// the seed of the session
+    pub seed: u64,
+    /// whether playing with the secret difficulty
+    pub secret: bool,
+    /// the grid size of every level, if forced on the command line
+    pub grid: Option<(u8, u8)>,
+    /// the level in progress
+    pub level: u8,
+    /// the current state of the tiles
+    pub tiles: Tiles,
+    /// the progress within the current attempt at the level
+    pub attempt: Attempt,
+    /// the statistics of the session so far,
+    /// the last entry being the level in progress
+    pub stats: SessionStats,
+}
+
+impl SavedGame {
+    /// Load the saved game from disk, if there is a valid one.
+    pub fn load() -> Option<Self> {
+        let payload = storage::read_record(FILENAME, MAGIC, VERSION)?;
+        Self::decode(&payload)
+    }
+
+    fn decode(payload: &[u8]) -> Option<Self> {
+        let mut reader = Reader::new(payload);
+        let mode = GameMode::from_u8(reader.u8()?)?;
+        let seed = reader.u64()?;
+        let secret = reader.u8()? != 0;
+        let forced_grid = reader.u8()? != 0;
+        let grid = (reader.u8()?, reader.u8()?);
+        let grid = forced_grid.then_some(grid);
+        let level = reader.u8()?;
+
+        let cols = reader.u8()?;
+        let rows = reader.u8()?;
+        let empty_x = reader.u8()?;
+        let empty_y = reader.u8()?;
+        let tiles = reader.bytes(cols as usize * rows as usize)?.to_vec();
+        let tiles = Tiles::from_parts(cols, rows, tiles, empty_x, empty_y)?;
+
+        let attempt = Attempt {
+            moves: reader.u32()?,
+            ticks: reader.u32()?,
+        };
+
+        let count = reader.u16()?;
+        let mut stats = SessionStats::new();
+        for _ in 0..count {
+            stats.levels.push(decode_level_stats(&mut reader)?);
+        }
+
+        if !reader.is_empty() {
+            return None;
+        }
+        // the level in progress must match the tiles
+        let current = stats.levels.last()?;
+        if current.level != level || (current.cols, current.rows) != (cols, rows) {
+            return None;
+        }
+        if grid.is_some_and(|grid| grid != (cols, rows)) {
+            return None;
+        }
+
+        Some(SavedGame {
+            mode,
+            seed,
+            secret,
+            grid,
+            level,
+            tiles,
+            attempt,
+            stats,
+        })
+    }
+
+    /// Save the game to disk.
+    pub fn save(&self) -> Result<(), storage::Error> {
+        let tiles = &self.tiles;
+        let mut payload = Vec::new();
+        payload.push(self.mode as u8);
+        payload.extend_from_slice(&self.seed.to_le_bytes());
+        payload.push(self.secret as u8);
+        match self.grid {
+            Some((cols, rows)) => payload.extend_from_slice(&[1, cols, rows]),
+            None => payload.extend_from_slice(&[0, 0, 0]),
+        }
+        payload.push(self.level);
+        payload.extend_from_slice(&[tiles.cols, tiles.rows, tiles.empty_x, tiles.empty_y]);
+        payload.extend_from_slice(&tiles.tiles);
+        payload.extend_from_slice(&self.attempt.moves.to_le_bytes());
+        payload.extend_from_slice(&self.attempt.ticks.to_le_bytes());
+        payload.extend_from_slice(&(self.stats.levels.len() as u16).to_le_bytes());
+        for level_stats in &self.stats.levels {
+            encode_level_stats(level_stats, &mut payload);
+        }
+        storage::write_record(FILENAME, MAGIC, VERSION, &payload)
+    }
+
+    /// Whether there is a valid saved game on disk
+    pub fn exists() -> bool {
+        Self::load().is_some()
+    }
+
+    /// Remove the saved game from disk.
+    pub fn delete() {
+        storage::remove_file(FILENAME);
+    }
+}
+
+fn encode_level_stats(stats: &LevelStats, out: &mut Vec<u8>) {
+    out.extend_from_slice(&[stats.level, stats.cols, stats.rows]);
+    out.extend_from_slice(&stats.seed.to_le_bytes());
+    out.extend_from_slice(&stats.moves.to_le_bytes());
+    out.extend_from_slice(&stats.ticks.to_le_bytes());
+    out.extend_from_slice(&stats.hints.to_le_bytes());
+    out.extend_from_slice(&stats.undos.to_le_bytes());
+    out.extend_from_slice(&stats.peeks.to_le_bytes());
+    match stats.optimal_moves {
+        Some(moves) => {
+            out.push(1);
+            out.extend_from_slice(&moves.to_le_bytes());
+        }
+        None => {
+            out.push(0);
+            out.extend_from_slice(&0_u32.to_le_bytes());
+        }
+    }
+    out.push(stats.solved as u8);
+}
+
+fn decode_level_stats(reader: &mut Reader) -> Option<LevelStats> {
+    let level = reader.u8()?;
+    let cols = reader.u8()?;
+    let rows = reader.u8()?;
+    let seed = reader.u64()?;
+    let moves = reader.u32()?;
+    let ticks = reader.u32()?;
+    let hints = reader.u32()?;
+    let undos = reader.u32()?;
+    let peeks = reader.u32()?;
+    let has_optimal = reader.u8()? != 0;
+    let optimal_moves = reader.u32()?;
+    let solved = reader.u8()? != 0;
+    Some(LevelStats {
+        level,
+        cols,
+        rows,
+        seed,
+        moves,
+        ticks,
+        hints,
+        undos,
+        peeks,
+        optimal_moves: has_optimal.then_some(optimal_moves),
+        solved,
+    })
+}
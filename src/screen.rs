@@ -0,0 +1,261 @@
+//! Off-screen back buffer for video mode 13h.
+//!
+//! Everything is drawn into the back buffer first,
+//! keeping track of the regions which changed (dirty rectangles).
+//! [`present`] then waits for the vertical retrace
+//! and copies only those regions to video memory,
+//! so that large changes do not tear.
+use alloc::vec::Vec;
+use core::ffi::c_void;
+use core::ptr::{addr_of, addr_of_mut};
+use dos_x::vga::vsync;
+
+extern "C" {
+    fn dosmemput(buffer: *const c_void, length: usize, offset: u32);
+}
+
+/// Width of the screen in pixels
+pub const WIDTH: u32 = 320;
+
+/// Height of the screen in pixels
+pub const HEIGHT: u32 = 200;
+
+/// Linear address of the VGA frame buffer
+const VIDEO_MEMORY: u32 = 0xA0000;
+
+/// Beyond this many dirty rectangles,
+/// they are merged into one
+const MAX_DIRTY_RECTS: usize = 16;
+
+/// A rectangle within the screen
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    /// Clip a rectangle to the screen,
+    /// returning `None` if nothing is left.
+    fn clipped(x: i32, y: i32, width: u32, height: u32) -> Option<Self> {
+        let x0 = x.max(0);
+        let y0 = y.max(0);
+        let x1 = (x + width as i32).min(WIDTH as i32);
+        let y1 = (y + height as i32).min(HEIGHT as i32);
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+        Some(Rect {
+            x: x0 as u32,
+            y: y0 as u32,
+            width: (x1 - x0) as u32,
+            height: (y1 - y0) as u32,
+        })
+    }
+
+    fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.x + other.width <= self.x + self.width
+            && other.y + other.height <= self.y + self.height
+    }
+
+    /// The smallest rectangle covering both rectangles
+    fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+/// The whole screen
+const FULL_SCREEN: Rect = Rect {
+    x: 0,
+    y: 0,
+    width: WIDTH,
+    height: HEIGHT,
+};
+
+/// The back buffer, in the same layout as video memory
+static mut BUFFER: [u8; (WIDTH * HEIGHT) as usize] = [0; (WIDTH * HEIGHT) as usize];
+
+/// The regions of the back buffer not yet copied to video memory
+static mut DIRTY: Vec<Rect> = Vec::new();
+
+fn buffer() -> &'static mut [u8; (WIDTH * HEIGHT) as usize] {
+    unsafe { &mut *addr_of_mut!(BUFFER) }
+}
+
+/// Record a region of the back buffer as changed.
+fn mark_dirty(rect: Rect) {
+    let dirty = unsafe { &mut *addr_of_mut!(DIRTY) };
+    if dirty.iter().any(|r| r.contains(&rect)) {
+        return;
+    }
+    dirty.retain(|r| !rect.contains(r));
+    if dirty.len() < MAX_DIRTY_RECTS {
+        dirty.push(rect);
+    } else {
+        let merged = dirty.iter().fold(rect, |acc, r| acc.union(r));
+        dirty.clear();
+        dirty.push(merged);
+    }
+}
+
+/// Fill a rectangle with a single color.
+pub fn draw_rect(x: i32, y: i32, width: u32, height: u32, color: u8) {
+    if let Some(rect) = fill_rect(buffer(), x, y, width, height, color) {
+        mark_dirty(rect);
+    }
+}
+
+/// Draw a filled rectangle into a full screen image (320x200)
+/// other than the screen itself.
+pub fn draw_rect_into(target: &mut [u8], x: i32, y: i32, width: u32, height: u32, color: u8) {
+    fill_rect(target, x, y, width, height, color);
+}
+
+/// Fill a rectangle of a full screen image,
+/// returning the part of it within the image.
+fn fill_rect(
+    target: &mut [u8],
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    color: u8,
+) -> Option<Rect> {
+    let rect = Rect::clipped(x, y, width, height)?;
+    for row in rect.y..rect.y + rect.height {
+        let start = (row * WIDTH + rect.x) as usize;
+        target[start..start + rect.width as usize].fill(color);
+    }
+    Some(rect)
+}
+
+/// Draw a horizontal line.
+pub fn draw_hline(x: i32, y: i32, length: u32, color: u8) {
+    draw_rect(x, y, length, 1, color);
+}
+
+/// Draw a vertical line.
+pub fn draw_vline(x: i32, y: i32, length: u32, color: u8) {
+    draw_rect(x, y, 1, length, color);
+}
+
+/// Copy a rectangle of an image onto the screen.
+///
+/// `origin` is the rectangle in the image (x, y, width, height)
+/// and `target` the position of its top left corner on the screen.
+pub fn blit_rect(
+    data: &[u8],
+    (data_width, data_height): (u32, u32),
+    (origin_x, origin_y, width, height): (u32, u32, u32, u32),
+    (target_x, target_y): (i32, i32),
+) {
+    // keep within the bounds of the image
+    let width = width.min(data_width.saturating_sub(origin_x));
+    let height = height.min(data_height.saturating_sub(origin_y));
+    let Some(rect) = Rect::clipped(target_x, target_y, width, height) else {
+        return;
+    };
+    // skip whatever was clipped on the top left
+    let origin_x = origin_x + (rect.x as i32 - target_x) as u32;
+    let origin_y = origin_y + (rect.y as i32 - target_y) as u32;
+
+    let buffer = buffer();
+    for row in 0..rect.height {
+        let source = ((origin_y + row) * data_width + origin_x) as usize;
+        let target = ((rect.y + row) * WIDTH + rect.x) as usize;
+        buffer[target..target + rect.width as usize]
+            .copy_from_slice(&data[source..source + rect.width as usize]);
+    }
+    mark_dirty(rect);
+}
+
+/// Copy a full screen image (320x200) onto the screen.
+pub fn draw_buffer(data: &[u8]) {
+    let buffer = buffer();
+    let len = buffer.len();
+    buffer.copy_from_slice(&data[..len]);
+    mark_dirty(FULL_SCREEN);
+}
+
+/// Copy single pixels of a full screen image (320x200) onto the screen,
+/// at the given offsets.
+///
+/// Only the rows where pixels actually changed are marked dirty,
+/// each run of them as one rectangle.
+pub fn copy_pixels(data: &[u8], offsets: impl IntoIterator<Item = usize>) {
+    let buffer = buffer();
+    // the span of changed columns in each row (first, last)
+    let mut changed = [None::<(u32, u32)>; HEIGHT as usize];
+    for offset in offsets {
+        if buffer[offset] == data[offset] {
+            continue;
+        }
+        buffer[offset] = data[offset];
+        let x = offset as u32 % WIDTH;
+        let span = &mut changed[offset / WIDTH as usize];
+        *span = Some(match *span {
+            Some((x0, x1)) => (x0.min(x), x1.max(x)),
+            None => (x, x),
+        });
+    }
+
+    let mut run: Option<Rect> = None;
+    for (y, span) in (0..).zip(changed) {
+        let row = span.map(|(x0, x1)| Rect {
+            x: x0,
+            y,
+            width: x1 - x0 + 1,
+            height: 1,
+        });
+        run = match (run, row) {
+            (Some(run), Some(row)) => Some(run.union(&row)),
+            (None, row) => row,
+            (Some(run), None) => {
+                mark_dirty(run);
+                None
+            }
+        };
+    }
+    if let Some(rect) = run {
+        mark_dirty(rect);
+    }
+}
+
+/// Take a copy of everything drawn so far,
+/// as a full screen image.
+pub fn snapshot() -> Vec<u8> {
+    buffer().to_vec()
+}
+
+/// Wait for the vertical retrace
+/// and copy everything drawn since the last call to video memory.
+pub fn present() {
+    let dirty = unsafe { &mut *addr_of_mut!(DIRTY) };
+    let buffer = unsafe { &*addr_of!(BUFFER) };
+    unsafe {
+        vsync();
+    }
+    for rect in dirty.drain(..) {
+        for row in rect.y..rect.y + rect.height {
+            let start = row * WIDTH + rect.x;
+            unsafe {
+                dosmemput(
+                    buffer[start as usize..].as_ptr() as *const c_void,
+                    rect.width as usize,
+                    VIDEO_MEMORY + start,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,360 @@
+//! Player settings,
+//! kept in the `[options]` section of the configuration file
+use alloc::format;
+use alloc::string::String;
+
+use crate::anim::{Easing, SlideSpeed};
+use crate::border::Border;
+use crate::config;
+use crate::input::Controls;
+use crate::joystick::Calibration;
+use crate::numbers::TileStyle;
+use crate::transition::Transition;
+use crate::GameMode;
+
+/// The settings which the player can change in the options menu
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Settings {
+    /// whether to play sounds through the PC speaker
+    pub sound: bool,
+    /// the game mode for new games
+    pub mode: GameMode,
+    /// whether to play with the secret difficulty (once unlocked)
+    pub secret: bool,
+    /// whether to show the heads-up display while playing
+    pub hud: bool,
+    /// whether to play without previewing the solved picture
+    pub hardcore: bool,
+    /// how long it takes for a tile to slide
+    pub slide_speed: SlideSpeed,
+    /// the curve followed by sliding tiles
+    pub easing: Easing,
+    /// how the board appears when a level starts
+    pub transition: Transition,
+    /// whether to outline the tiles in their home position
+    pub highlight: bool,
+    /// what the tiles show
+    pub tile_style: TileStyle,
+    /// what is drawn around each tile
+    pub border: Border,
+    /// whether the arrow keys move tiles or a cursor
+    pub controls: Controls,
+    /// whether the arrow keys move the empty slot instead of a tile
+    pub invert: bool,
+    /// the calibration of the joystick, or `None` to ignore it
+    pub joystick: Option<Calibration>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            sound: true,
+            mode: GameMode::Classic,
+            secret: false,
+            hud: false,
+            hardcore: false,
+            slide_speed: SlideSpeed::Normal,
+            easing: Easing::EaseOut,
+            transition: Transition::Random,
+            highlight: false,
+            tile_style: TileStyle::Picture,
+            border: Border::None,
+            controls: Controls::Direct,
+            invert: false,
+            joystick: None,
+        }
+    }
+}
+
+/// Names of the game modes in the configuration
+const MODE_NAMES: [&str; 3] = ["classic", "timeattack", "movelimit"];
+/// Names of the tile styles in the configuration
+const TILE_STYLE_NAMES: [&str; 3] = ["picture", "numbered", "numbers"];
+/// Names of the borders in the configuration
+const BORDER_NAMES: [&str; 3] = ["none", "lines", "bevel"];
+/// Names of the control schemes in the configuration
+const CONTROLS_NAMES: [&str; 2] = ["direct", "cursor"];
+/// Names of the slide speeds in the configuration
+const SLIDE_SPEED_NAMES: [&str; 3] = ["slow", "normal", "fast"];
+/// Names of the easing curves in the configuration
+const EASING_NAMES: [&str; 3] = ["linear", "easeout", "easeinout"];
+/// Names of the transitions in the configuration
+const TRANSITION_NAMES: [&str; 6] = ["random", "tiles", "dissolve", "wipeh", "wipev", "flip"];
+
+/// Look up a value in a table of names (ignoring case),
+/// returning its index.
+fn parse_name(names: &[&str], value: &str) -> Option<u8> {
+    names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(value))
+        .map(|i| i as u8)
+}
+
+/// Interpret an on/off value (also yes/no, true/false and 1/0).
+fn parse_bool(value: &str) -> Option<bool> {
+    const ON: [&str; 4] = ["on", "yes", "true", "1"];
+    const OFF: [&str; 4] = ["off", "no", "false", "0"];
+    if ON.iter().any(|v| v.eq_ignore_ascii_case(value)) {
+        Some(true)
+    } else if OFF.iter().any(|v| v.eq_ignore_ascii_case(value)) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Interpret a joystick calibration:
+/// the top left corner, the center and the bottom right corner,
+/// as six numbers separated by commas.
+fn parse_calibration(value: &str) -> Option<Calibration> {
+    let mut numbers = value.split(',').map(|n| n.trim().parse::<u16>());
+    let mut next = || numbers.next()?.ok();
+    let calibration = Calibration {
+        min: [next()?, next()?],
+        center: [next()?, next()?],
+        max: [next()?, next()?],
+    };
+    (next().is_none() && calibration.is_valid()).then_some(calibration)
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+impl Settings {
+    /// Change the settings according to the `[options]` section of a configuration,
+    /// ignoring any option which is unknown or has an invalid value.
+    pub fn apply_config(&mut self, text: &str) {
+        for entry in config::entries(text) {
+            if !entry.section.eq_ignore_ascii_case("options") {
+                continue;
+            }
+            let value = entry.value;
+            let flag = |setting: &mut bool| {
+                if let Some(on) = parse_bool(value) {
+                    *setting = on;
+                }
+            };
+            match entry.key.to_ascii_lowercase().as_str() {
+                // the PC speaker is the only sound device
+                "sound" if value.eq_ignore_ascii_case("speaker") => self.sound = true,
+                "sound" => flag(&mut self.sound),
+                "secret" => flag(&mut self.secret),
+                "hud" => flag(&mut self.hud),
+                "hardcore" => flag(&mut self.hardcore),
+                "highlight" => flag(&mut self.highlight),
+                "invert" => flag(&mut self.invert),
+                "mode" => {
+                    if let Some(mode) = parse_name(&MODE_NAMES, value).and_then(GameMode::from_u8) {
+                        self.mode = mode;
+                    }
+                }
+                "tiles" => {
+                    if let Some(style) =
+                        parse_name(&TILE_STYLE_NAMES, value).and_then(TileStyle::from_u8)
+                    {
+                        self.tile_style = style;
+                    }
+                }
+                "border" => {
+                    if let Some(border) = parse_name(&BORDER_NAMES, value).and_then(Border::from_u8)
+                    {
+                        self.border = border;
+                    }
+                }
+                "controls" => {
+                    if let Some(controls) =
+                        parse_name(&CONTROLS_NAMES, value).and_then(Controls::from_u8)
+                    {
+                        self.controls = controls;
+                    }
+                }
+                "speed" => {
+                    if let Some(speed) =
+                        parse_name(&SLIDE_SPEED_NAMES, value).and_then(SlideSpeed::from_u8)
+                    {
+                        self.slide_speed = speed;
+                    }
+                }
+                "easing" => {
+                    if let Some(easing) = parse_name(&EASING_NAMES, value).and_then(Easing::from_u8)
+                    {
+                        self.easing = easing;
+                    }
+                }
+                "transition" => {
+                    if let Some(transition) =
+                        parse_name(&TRANSITION_NAMES, value).and_then(Transition::from_u8)
+                    {
+                        self.transition = transition;
+                    }
+                }
+                "joystick" if parse_bool(value) == Some(false) => self.joystick = None,
+                "joystick" => {
+                    if let Some(calibration) = parse_calibration(value) {
+                        self.joystick = Some(calibration);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The options of the `[options]` section describing the settings,
+    /// as keys and values
+    fn config_values(&self) -> [(&'static str, String); 14] {
+        let joystick = match self.joystick {
+            Some(c) => format!(
+                "{}, {}, {}, {}, {}, {}",
+                c.min[0], c.min[1], c.center[0], c.center[1], c.max[0], c.max[1]
+            ),
+            None => on_off(false).into(),
+        };
+        [
+            ("sound", on_off(self.sound).into()),
+            ("mode", MODE_NAMES[self.mode as usize].into()),
+            ("controls", CONTROLS_NAMES[self.controls as usize].into()),
+            ("invert", on_off(self.invert).into()),
+            ("joystick", joystick),
+            ("secret", on_off(self.secret).into()),
+            ("hud", on_off(self.hud).into()),
+            ("hardcore", on_off(self.hardcore).into()),
+            ("highlight", on_off(self.highlight).into()),
+            ("tiles", TILE_STYLE_NAMES[self.tile_style as usize].into()),
+            ("border", BORDER_NAMES[self.border as usize].into()),
+            ("speed", SLIDE_SPEED_NAMES[self.slide_speed as usize].into()),
+            ("easing", EASING_NAMES[self.easing as usize].into()),
+            (
+                "transition",
+                TRANSITION_NAMES[self.transition as usize].into(),
+            ),
+        ]
+    }
+
+    /// Set every option of the `[options]` section of a configuration,
+    /// keeping everything else in it (comments and unknown options too).
+    pub fn write_config(&self, text: &str) -> String {
+        self.config_values()
+            .into_iter()
+            .fold(String::from(text), |text, (key, value)| {
+                config::set_value(&text, "options", key, &value)
+            })
+    }
+
+    /// Write the settings to the configuration file,
+    /// keeping everything else in it.
+    pub fn save_config(&self) -> Result<(), crate::storage::Error> {
+        config::update(|text| self.write_config(text))
+    }
+
+    /// Write a single option to the configuration file,
+    /// keeping everything else in it.
+    pub fn save_config_option(&self, key: &str) -> Result<(), crate::storage::Error> {
+        let Some((key, value)) = self.config_values().into_iter().find(|(k, _)| *k == key) else {
+            return Ok(());
+        };
+        config::update(|text| config::set_value(text, "options", key, &value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_config_reads_options() {
+        let text = "[game]\nsound = off\n[Options]\nSound = OFF\nMode = TimeAttack\nhud = yes\ntiles = numbers\n";
+        let mut settings = Settings::default();
+        settings.apply_config(text);
+        assert!(!settings.sound);
+        assert_eq!(settings.mode, GameMode::TimeAttack);
+        assert!(settings.hud);
+        assert_eq!(settings.tile_style, TileStyle::Numbers);
+    }
+
+    #[test]
+    fn apply_config_ignores_unknown_and_invalid_values() {
+        let text = "[options]\nsound = maybe\nmode = fastest\ncolour = blue\nborder =\n";
+        let mut settings = Settings::default();
+        settings.apply_config(text);
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn apply_config_accepts_speaker_as_sound() {
+        let mut settings = Settings {
+            sound: false,
+            ..Settings::default()
+        };
+        settings.apply_config("[options]\nsound = speaker\n");
+        assert!(settings.sound);
+    }
+
+    #[test]
+    fn apply_config_reads_the_joystick_calibration() {
+        let mut settings = Settings::default();
+        settings.apply_config("[options]\njoystick = 10, 20, 150, 160, 300, 310\n");
+        assert_eq!(
+            settings.joystick,
+            Some(Calibration {
+                min: [10, 20],
+                center: [150, 160],
+                max: [300, 310],
+            })
+        );
+        settings.apply_config("[options]\njoystick = off\n");
+        assert_eq!(settings.joystick, None);
+        // the center must lie between the corners
+        settings.apply_config("[options]\njoystick = 10, 20, 5, 160, 300, 310\n");
+        assert_eq!(settings.joystick, None);
+    }
+
+    #[test]
+    fn write_config_round_trip() {
+        let settings = Settings {
+            sound: false,
+            mode: GameMode::MoveLimit,
+            secret: true,
+            hud: true,
+            hardcore: true,
+            slide_speed: SlideSpeed::Fast,
+            easing: Easing::Linear,
+            transition: Transition::Dissolve,
+            highlight: true,
+            tile_style: TileStyle::PictureNumbered,
+            border: Border::Bevel,
+            controls: Controls::Cursor,
+            invert: true,
+            joystick: Some(Calibration {
+                min: [10, 20],
+                center: [150, 160],
+                max: [300, 310],
+            }),
+        };
+        let text = settings.write_config("");
+        let mut read = Settings::default();
+        read.apply_config(&text);
+        assert_eq!(read, settings);
+    }
+
+    #[test]
+    fn write_config_keeps_comments_and_unknown_options() {
+        let text =
+            "[options]\r\n; my favourite\r\nhud = on\r\ncolour = blue\r\n[keys]\r\nquit = Esc\r\n";
+        let written = Settings::default().write_config(text);
+        assert!(
+            written.starts_with("[options]\r\n; my favourite\r\nhud = off\r\ncolour = blue\r\n")
+        );
+        assert!(written.ends_with("[keys]\r\nquit = Esc\r\n"));
+        let mut read = Settings {
+            hud: true,
+            ..Settings::default()
+        };
+        read.apply_config(&written);
+        assert_eq!(read, Settings::default());
+    }
+}
@@ -1,17 +1,115 @@
-//! Simple game stats
+//! Game session statistics
+use alloc::vec::Vec;
+use dos_x::println;
 
-/// Total number of tile moves
-static mut TOTAL_MOVES: u32 = 0;
+use crate::timer::ticks_to_seconds;
 
-#[inline]
-pub fn add_move() {
-    // DOS is single threaded so...
-    unsafe {
-        TOTAL_MOVES += 1;
+/// Statistics of a single level played in the session
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelStats {
+    /// the level number (starting from 0)
+    pub level: u8,
+    /// the number of columns in the puzzle
+    pub cols: u8,
+    /// the number of rows in the puzzle
+    pub rows: u8,
+    /// the seed of the session which shuffled the tiles
+    pub seed: u64,
+    /// the number of tile moves
+    pub moves: u32,
+    /// the time spent playing the level, in BIOS ticks
+    pub ticks: u32,
+    /// the number of hints requested
+    pub hints: u32,
+    /// the number of moves undone
+    pub undos: u32,
+    /// the number of times the solved picture was previewed
+    pub peeks: u32,
+    /// the smallest number of moves needed to solve the puzzle,
+    /// if it could be calculated
+    pub optimal_moves: Option<u32>,
+    /// whether the puzzle was solved
+    pub solved: bool,
+}
+
+/// Statistics of all levels played in the session
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SessionStats {
+    pub levels: Vec<LevelStats>,
+}
+
+impl SessionStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Begin recording a new level,
+    /// returning the record to update while the level is played.
+    pub fn start_level(
+        &mut self,
+        level: u8,
+        (cols, rows): (u8, u8),
+        seed: u64,
+        optimal_moves: Option<u32>,
+    ) -> &mut LevelStats {
+        self.levels.push(LevelStats {
+            level,
+            cols,
+            rows,
+            seed,
+            moves: 0,
+            ticks: 0,
+            hints: 0,
+            undos: 0,
+            peeks: 0,
+            optimal_moves,
+            solved: false,
+        });
+        self.levels.last_mut().unwrap()
+    }
+
+    /// Total number of tile moves across all levels
+    pub fn total_moves(&self) -> u32 {
+        self.levels.iter().map(|l| l.moves).sum()
+    }
+
+    /// Print a table with the statistics of each level (in text mode).
+    pub fn print_results(&self) {
+        println!("Level  Grid  Moves  Optimal   Time  Hint  Undo  Peek  Seed");
+        for l in &self.levels {
+            let seconds = ticks_to_seconds(l.ticks);
+            let status = if l.solved { ' ' } else { '*' };
+            println!(
+                "{:>4}{} {:>3}x{:<2} {:>5}  {:>7}  {:>2}:{:02}  {:>4}  {:>4}  {:>4}  {}",
+                l.level + 1,
+                status,
+                l.cols,
+                l.rows,
+                l.moves,
+                OrDash(l.optimal_moves),
+                seconds / 60,
+                seconds % 60,
+                l.hints,
+                l.undos,
+                l.peeks,
+                l.seed,
+            );
+        }
+        println!(
+            "The tiles were moved {} times in total. (* = unsolved)",
+            self.total_moves()
+        );
     }
 }
 
-#[inline]
-pub fn total_moves() -> u32 {
-    unsafe { TOTAL_MOVES }
+/// Display helper for printing a dash in place of a missing number
+struct OrDash(Option<u32>);
+
+impl core::fmt::Display for OrDash {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.0 {
+            Some(n) => core::fmt::Display::fmt(&n, f),
+            None => f.pad("-"),
+        }
+    }
 }
@@ -0,0 +1,201 @@
+//! Reading and writing small data files next to the executable.
+//!
+//! Each data file is a record with this layout:
+//!
+//! ```none
+//! +-------+---------+---------+----------+
+//! | magic | version | payload | checksum |
+//! |  4 B  |   1 B   |   ...   |   16 B   |
+//! +-------+---------+---------+----------+
+//! ```
+//!
+//! The checksum is the MD5 digest of everything before it,
+//! so that old, truncated or tampered files are rejected.
+use alloc::vec::Vec;
+use core::ffi::{c_char, c_int, c_void, CStr};
+
+extern "C" {
+    fn fopen(filename: *const c_char, mode: *const c_char) -> *mut c_void;
+    fn fwrite(ptr: *const c_void, size: usize, nmemb: usize, stream: *mut c_void) -> usize;
+    fn fclose(stream: *mut c_void) -> c_int;
+    fn remove(filename: *const c_char) -> c_int;
+    fn rename(old: *const c_char, new: *const c_char) -> c_int;
+}
+
+/// The file written first,
+/// which then takes the place of the file to replace
+const TEMP_FILENAME: &CStr = c"TILERS.$$$";
+
+/// The name the file to replace is moved to
+/// until the new file is in place
+const BACKUP_FILENAME: &CStr = c"TILERS.BAK";
+
+/// Length of the record header (magic + version)
+const HEADER_LEN: usize = 5;
+
+/// Length of the record checksum
+const CHECKSUM_LEN: usize = 16;
+
+/// An error writing a data file
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Error {
+    /// The file could not be created
+    /// (e.g. the disk is read-only or write protected)
+    Create,
+    /// The file could not be written in full
+    /// (e.g. the disk is full)
+    Write,
+    /// The new file could not take the place of the previous one
+    Replace,
+    /// The previous file, to be changed, could not be read
+    Read,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Create => f.write_str("could not create file"),
+            Error::Write => f.write_str("could not write file"),
+            Error::Replace => f.write_str("could not replace file"),
+            Error::Read => f.write_str("could not read file"),
+        }
+    }
+}
+
+/// Read a data record from a file.
+///
+/// Returns the payload of the record,
+/// or `None` if the file is missing, unreadable,
+/// of a different kind or version, or corrupt.
+pub fn read_record(filename: &CStr, magic: &[u8; 4], version: u8) -> Option<Vec<u8>> {
+    read_any_record(filename, magic)
+        .filter(|(v, _)| *v == version)
+        .map(|(_, payload)| payload)
+}
+
+/// Read a data record from a file, whatever its version.
+///
+/// Returns the version and the payload of the record,
+/// or `None` if the file is missing, unreadable,
+/// of a different kind, or corrupt.
+pub fn read_any_record(filename: &CStr, magic: &[u8; 4]) -> Option<(u8, Vec<u8>)> {
+    let mut file = dos_x::fs::File::open(filename).ok()?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).ok()?;
+
+    if data.len() < HEADER_LEN + CHECKSUM_LEN || data[0..4] != magic[..] {
+        return None;
+    }
+    let (content, checksum) = data.split_at(data.len() - CHECKSUM_LEN);
+    if md5::compute(content).0[..] != *checksum {
+        return None;
+    }
+    Some((content[4], content[HEADER_LEN..].to_vec()))
+}
+
+/// Write a data record to a file,
+/// replacing any previous contents.
+pub fn write_record(
+    filename: &CStr,
+    magic: &[u8; 4],
+    version: u8,
+    payload: &[u8],
+) -> Result<(), Error> {
+    let mut data = Vec::with_capacity(HEADER_LEN + payload.len() + CHECKSUM_LEN);
+    data.extend_from_slice(magic);
+    data.push(version);
+    data.extend_from_slice(payload);
+    let checksum = md5::compute(&data);
+    data.extend_from_slice(&checksum.0);
+    write_file(filename, &data)
+}
+
+/// Write the given bytes to a file,
+/// replacing any previous contents.
+///
+/// The bytes go to a temporary file first,
+/// so that the previous file is left intact
+/// if writing fails (e.g. when the disk is full).
+pub fn write_file(filename: &CStr, data: &[u8]) -> Result<(), Error> {
+    unsafe {
+        let file = fopen(TEMP_FILENAME.as_ptr(), c"wb".as_ptr());
+        if file.is_null() {
+            return Err(Error::Create);
+        }
+        let written = fwrite(data.as_ptr() as *const c_void, 1, data.len(), file);
+        let closed = fclose(file);
+        if written != data.len() || closed != 0 {
+            remove(TEMP_FILENAME.as_ptr());
+            return Err(Error::Write);
+        }
+        // DOS cannot rename over an existing file,
+        // so the previous one is moved out of the way first
+        // (this fails harmlessly if there is none)
+        remove(BACKUP_FILENAME.as_ptr());
+        let backed_up = rename(filename.as_ptr(), BACKUP_FILENAME.as_ptr()) == 0;
+        if rename(TEMP_FILENAME.as_ptr(), filename.as_ptr()) != 0 {
+            // put the previous file back
+            if backed_up {
+                rename(BACKUP_FILENAME.as_ptr(), filename.as_ptr());
+            }
+            remove(TEMP_FILENAME.as_ptr());
+            return Err(Error::Replace);
+        }
+        remove(BACKUP_FILENAME.as_ptr());
+    }
+    Ok(())
+}
+
+/// Delete a file, if it exists.
+pub fn remove_file(filename: &CStr) {
+    unsafe {
+        remove(filename.as_ptr());
+    }
+}
+
+/// Helper for decoding little endian values from a record payload
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    /// Take the next `len` bytes
+    pub fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Option<u64> {
+        self.bytes(8).map(|b| {
+            let mut v = [0; 8];
+            v.copy_from_slice(b);
+            u64::from_le_bytes(v)
+        })
+    }
+
+    /// Whether all bytes have been read
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
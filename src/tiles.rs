@@ -1,4 +1,5 @@
 //! Module for the logic of setting up and moving the tiles of the puzzle
+use alloc::vec;
 use alloc::vec::Vec;
 
 use tinyrand::RandRange;
@@ -20,7 +21,7 @@ use tinyrand::RandRange;
 ///
 /// The tile of index 0 is assumed to be the empty tile,
 /// but the x and y coordinate is saved separately.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Tiles {
     /// the indices of the individual tiles,
     /// in column-first order
@@ -71,6 +72,44 @@ impl Tiles {
         }
     }
 
+    /// Rebuild a tile state from its parts,
+    /// as previously saved.
+    ///
+    /// Returns `None` if the parts do not describe a valid state.
+    pub fn from_parts(
+        cols: u8,
+        rows: u8,
+        tiles: Vec<u8>,
+        empty_x: u8,
+        empty_y: u8,
+    ) -> Option<Self> {
+        if !(1..=16).contains(&cols) || !(1..=16).contains(&rows) {
+            return None;
+        }
+        if tiles.len() != cols as usize * rows as usize || empty_x >= cols || empty_y >= rows {
+            return None;
+        }
+        // each tile must appear exactly once
+        let mut seen = [false; 256];
+        for &tile in &tiles {
+            if tile as usize >= tiles.len() || seen[tile as usize] {
+                return None;
+            }
+            seen[tile as usize] = true;
+        }
+        // and the empty tile must be where it is said to be
+        if tiles[empty_y as usize * cols as usize + empty_x as usize] != 0 {
+            return None;
+        }
+        Some(Tiles {
+            tiles,
+            rows,
+            cols,
+            empty_x,
+            empty_y,
+        })
+    }
+
     /// Get the x,y coordinates that the tile
     /// currently at the given coordinates is supposed to be at
     /// when the puzzle is solved.
@@ -98,6 +137,251 @@ impl Tiles {
         (index as u8 % self.cols, index as u8 / self.cols)
     }
 
+    /// Obtain a lower bound of the number of moves
+    /// needed to solve the puzzle.
+    ///
+    /// This is the sum of the Manhattan distances
+    /// of each tile to its home position.
+    pub fn min_moves(&self) -> u32 {
+        let mut total = 0;
+        for y in 0..self.rows {
+            for x in 0..self.cols {
+                if x == self.empty_x && y == self.empty_y {
+                    continue;
+                }
+                let (home_x, home_y) = self.position_of(x, y);
+                total += home_x.abs_diff(x) as u32 + home_y.abs_diff(y) as u32;
+            }
+        }
+        total
+    }
+
+    /// Calculate the smallest number of moves needed to solve the puzzle,
+    /// using an iterative deepening A* search.
+    ///
+    /// Gives up and returns `None`
+    /// if more than `max_nodes` states would need to be visited.
+    pub fn optimal_moves(&self, max_nodes: u32) -> Option<u32> {
+        self.optimal_solution(max_nodes)
+            .map(|solution| solution.len() as u32)
+    }
+
+    /// Find one of the shortest sequences of moves which solve the puzzle,
+    /// using an iterative deepening A* search.
+    ///
+    /// Gives up and returns `None`
+    /// if more than `max_nodes` states would need to be visited.
+    pub fn optimal_solution(&self, max_nodes: u32) -> Option<Vec<Move>> {
+        let mut state = self.clone();
+        let mut bound = state.min_moves();
+        let mut nodes = 0;
+        let mut path = Vec::new();
+        loop {
+            match state.search(&mut path, bound, &mut nodes, max_nodes) {
+                Search::Found => return Some(path),
+                Search::Exceeded(next_bound) => bound = next_bound,
+                Search::GaveUp => return None,
+            }
+        }
+    }
+
+    /// Suggest a move towards the solution
+    /// without searching beyond the tiles being put in place.
+    ///
+    /// The tiles are put in place a few at a time:
+    /// the rows from the bottom up, except for the top two,
+    /// then the columns of the top two rows from right to left,
+    /// and finally the tiles around the home of the empty slot.
+    /// Each step is solved exactly,
+    /// so following these moves always leads to the solution.
+    /// Returns `None` if the puzzle is already solved.
+    pub fn placement_move(&self) -> Option<Move> {
+        let mut locked = vec![false; self.tiles.len()];
+        for step in self.placement_steps() {
+            if step.iter().all(|&cell| self.tiles[cell as usize] == cell) {
+                // already in place, leave them there
+                for &cell in &step {
+                    locked[cell as usize] = true;
+                }
+                continue;
+            }
+            return self.placing_move(&step, &locked);
+        }
+        None
+    }
+
+    /// The cells of the board (which are also the tiles that belong in them)
+    /// in the order in which `placement_move` puts them in place,
+    /// grouped by step.
+    fn placement_steps(&self) -> Vec<Vec<u8>> {
+        let (cols, rows) = (self.cols as usize, self.rows as usize);
+        let cell = |x: usize, y: usize| (y * cols + x) as u8;
+        let mut steps = Vec::new();
+        for y in (2..rows).rev() {
+            for x in (2..cols).rev() {
+                steps.push(vec![cell(x, y)]);
+            }
+            // the last two tiles of a row go in together
+            steps.push((0..cols.min(2)).rev().map(|x| cell(x, y)).collect());
+        }
+        let top = rows.min(2);
+        for x in (2..cols).rev() {
+            steps.push((0..top).map(|y| cell(x, y)).collect());
+        }
+        steps.push(
+            (0..top)
+                .flat_map(|y| (0..cols.min(2)).map(move |x| cell(x, y)))
+                .filter(|&cell| cell != 0)
+                .collect(),
+        );
+        steps.retain(|step| !step.is_empty());
+        steps
+    }
+
+    /// The first move of a shortest sequence
+    /// which brings the given tiles (at most 3) to their home positions
+    /// without moving the tiles in the locked cells,
+    /// or `None` if there is no such sequence.
+    ///
+    /// This is a breadth-first search over the positions
+    /// of the empty slot and of the given tiles,
+    /// ignoring where the other tiles go.
+    fn placing_move(&self, tiles: &[u8], locked: &[bool]) -> Option<Move> {
+        const MOVES: [Move; 4] = [Move::Up, Move::Down, Move::Left, Move::Right];
+        // marks the state the search starts from
+        const START: u8 = u8::MAX;
+
+        let cols = self.cols as usize;
+        // number the free cells
+        let mut local = vec![None; self.tiles.len()];
+        let mut cells = Vec::new();
+        for (cell, _) in locked.iter().enumerate().filter(|(_, &locked)| !locked) {
+            local[cell] = Some(cells.len());
+            cells.push(cell);
+        }
+        let free = cells.len();
+        let tracked = tiles.len();
+        let encode = |positions: &[usize]| positions.iter().rev().fold(0, |acc, &p| acc * free + p);
+
+        // the empty slot, followed by the tiles to put in place
+        let mut positions = [0; 4];
+        positions[0] = local[self.empty_y as usize * cols + self.empty_x as usize]?;
+        let mut homes = [0; 3];
+        for (i, &tile) in tiles.iter().enumerate() {
+            let cell = self.tiles.iter().position(|&t| t == tile)?;
+            positions[i + 1] = local[cell]?;
+            homes[i] = local[tile as usize]?;
+        }
+        let start = encode(&positions[..=tracked]);
+
+        // the first move made towards each state visited (plus one),
+        // or 0 if not visited yet
+        let mut first = vec![0u8; free.pow(tracked as u32 + 1)];
+        first[start] = START;
+        let mut queue = vec![start];
+        let mut head = 0;
+        while let Some(&state) = queue.get(head) {
+            head += 1;
+            let mut rest = state;
+            for p in positions.iter_mut().take(tracked + 1) {
+                *p = rest % free;
+                rest /= free;
+            }
+            let empty = cells[positions[0]];
+            let (x, y) = ((empty % cols) as isize, (empty / cols) as isize);
+            for (i, m) in MOVES.into_iter().enumerate() {
+                // where the empty slot goes
+                let (dx, dy) = match m {
+                    Move::Up => (0, 1),
+                    Move::Down => (0, -1),
+                    Move::Left => (1, 0),
+                    Move::Right => (-1, 0),
+                };
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= cols as isize || ny >= self.rows as isize {
+                    continue;
+                }
+                let Some(target) = local[ny as usize * cols + nx as usize] else {
+                    continue;
+                };
+                let mut next = positions;
+                for p in next[1..=tracked].iter_mut() {
+                    if *p == target {
+                        *p = positions[0];
+                    }
+                }
+                next[0] = target;
+                let code = encode(&next[..=tracked]);
+                if first[code] != 0 {
+                    continue;
+                }
+                first[code] = if state == start {
+                    i as u8 + 1
+                } else {
+                    first[state]
+                };
+                if next[1..=tracked] == homes[..tracked] {
+                    return Some(MOVES[first[code] as usize - 1]);
+                }
+                queue.push(code);
+            }
+        }
+        None
+    }
+
+    /// One depth-first iteration of the optimal solution search,
+    /// continuing from the moves in `path`.
+    fn search(
+        &mut self,
+        path: &mut Vec<Move>,
+        bound: u32,
+        nodes: &mut u32,
+        max_nodes: u32,
+    ) -> Search {
+        let moves = path.len() as u32;
+        let estimate = self.min_moves();
+        if moves + estimate > bound {
+            return Search::Exceeded(moves + estimate);
+        }
+        if estimate == 0 {
+            return Search::Found;
+        }
+
+        *nodes += 1;
+        if *nodes > max_nodes {
+            return Search::GaveUp;
+        }
+
+        let last = path.last().copied();
+        let mut next_bound = u32::MAX;
+        for m in [Move::Up, Move::Down, Move::Left, Move::Right] {
+            // do not undo the previous move
+            if Some(m.opposite()) == last || !self.do_move(m) {
+                continue;
+            }
+            path.push(m);
+            let result = self.search(path, bound, nodes, max_nodes);
+            self.do_move(m.opposite());
+            match result {
+                Search::Found => return Search::Found,
+                Search::Exceeded(b) => next_bound = next_bound.min(b),
+                Search::GaveUp => return Search::GaveUp,
+            }
+            path.pop();
+        }
+        Search::Exceeded(next_bound)
+    }
+
+    /// Count the tiles which are already in their home position
+    /// (not counting the empty slot).
+    pub fn count_in_place(&self) -> u32 {
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|&(i, &tile)| tile != 0 && i == tile as usize)
+            .count() as u32
+    }
+
     pub fn is_won(&self) -> bool {
         self.tiles
             .iter()
@@ -115,6 +399,24 @@ impl Tiles {
         }
     }
 
+    /// The move which slides the tile at the given position towards the empty slot
+    /// and how many times it has to be done for that tile to reach the slot
+    /// (pushing along the tiles in between),
+    /// or `None` if the tile is not in line with the empty slot.
+    pub fn slide_towards(&self, x: u8, y: u8) -> Option<(Move, u8)> {
+        if y == self.empty_y && x > self.empty_x {
+            Some((Move::Left, x - self.empty_x))
+        } else if y == self.empty_y && x < self.empty_x {
+            Some((Move::Right, self.empty_x - x))
+        } else if x == self.empty_x && y > self.empty_y {
+            Some((Move::Up, y - self.empty_y))
+        } else if x == self.empty_x && y < self.empty_y {
+            Some((Move::Down, self.empty_y - y))
+        } else {
+            None
+        }
+    }
+
     /// Apply a move to the tiles.
     ///
     /// If the operation is invalid,
@@ -164,6 +466,53 @@ impl Tiles {
     }
 }
 
+/// The result of an optimal solution search iteration
+enum Search {
+    /// a solution was found, with its moves in the path searched
+    Found,
+    /// no solution within the bound,
+    /// the next bound to try is attached
+    Exceeded(u32),
+    /// the search took too long
+    GaveUp,
+}
+
+/// Suggests moves towards the solution, one at a time.
+///
+/// An optimal solution is followed when one can be found
+/// within the given number of states,
+/// otherwise the moves come from [`Tiles::placement_move`]
+/// until the player makes a move of their own.
+/// Either way, asking for hints over and over solves the puzzle.
+#[derive(Debug, Default, Clone)]
+pub struct Hints {
+    /// the rest of the optimal solution being followed, last move first
+    /// (empty when the search gave up)
+    plan: Vec<Move>,
+    /// the state of the tiles after the last hint,
+    /// so that a new search starts only if the player makes other moves
+    expected: Option<Tiles>,
+}
+
+impl Hints {
+    /// Suggest the next move for the given tiles,
+    /// or `None` if the puzzle is already solved.
+    pub fn next(&mut self, tiles: &Tiles, max_nodes: u32) -> Option<Move> {
+        if tiles.is_won() {
+            return None;
+        }
+        if self.expected.as_ref() != Some(tiles) {
+            self.plan = tiles.optimal_solution(max_nodes).unwrap_or_default();
+            self.plan.reverse();
+        }
+        let m = self.plan.pop().or_else(|| tiles.placement_move())?;
+        let mut expected = tiles.clone();
+        expected.do_move(m);
+        self.expected = Some(expected);
+        Some(m)
+    }
+}
+
 /// A player movement of a tile towards the empty slot.
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
 pub enum Move {
@@ -176,3 +525,206 @@ pub enum Move {
     /// move the left tile to the right
     Right,
 }
+
+impl Move {
+    /// The move which reverts this one
+    pub fn opposite(self) -> Move {
+        match self {
+            Move::Up => Move::Down,
+            Move::Down => Move::Up,
+            Move::Left => Move::Right,
+            Move::Right => Move::Left,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, VecDeque};
+    use tinyrand::{Seeded, Xorshift};
+
+    const MOVES: [Move; 4] = [Move::Up, Move::Down, Move::Left, Move::Right];
+
+    /// The smallest number of moves to solve every state of a board,
+    /// found by a breadth-first search from the solved state.
+    fn distances(cols: u8, rows: u8) -> HashMap<Vec<u8>, (Tiles, u32)> {
+        let solved = Tiles::new(cols, rows);
+        let mut distances = HashMap::new();
+        distances.insert(solved.tiles.clone(), (solved.clone(), 0));
+        let mut queue = VecDeque::from([(solved, 0)]);
+        while let Some((tiles, distance)) = queue.pop_front() {
+            for m in MOVES {
+                let mut next = tiles.clone();
+                if next.do_move(m) && !distances.contains_key(&next.tiles) {
+                    distances.insert(next.tiles.clone(), (next.clone(), distance + 1));
+                    queue.push_back((next, distance + 1));
+                }
+            }
+        }
+        distances
+    }
+
+    #[test]
+    fn solved_board_needs_no_moves() {
+        let tiles = Tiles::new(4, 3);
+        assert!(tiles.is_won());
+        assert_eq!(tiles.min_moves(), 0);
+        assert_eq!(tiles.optimal_moves(1), Some(0));
+    }
+
+    #[test]
+    fn one_move_away() {
+        let mut tiles = Tiles::new(4, 3);
+        assert!(tiles.do_move(Move::Up));
+        assert_eq!(tiles.min_moves(), 1);
+        assert_eq!(tiles.optimal_moves(100), Some(1));
+    }
+
+    #[test]
+    fn optimal_moves_of_every_2x2_board() {
+        let distances = distances(2, 2);
+        assert_eq!(distances.len(), 12);
+        for (tiles, distance) in distances.values() {
+            assert_eq!(tiles.optimal_moves(10_000), Some(*distance), "{:?}", tiles);
+        }
+        assert_eq!(distances.values().map(|(_, d)| *d).max(), Some(6));
+    }
+
+    #[test]
+    fn optimal_moves_of_every_3x2_board() {
+        let distances = distances(3, 2);
+        assert_eq!(distances.len(), 360);
+        for (tiles, distance) in distances.values() {
+            assert!(tiles.min_moves() <= *distance, "{:?}", tiles);
+            assert_eq!(tiles.optimal_moves(100_000), Some(*distance), "{:?}", tiles);
+        }
+        // the hardest 3x2 boards take 21 moves
+        assert_eq!(distances.values().map(|(_, d)| *d).max(), Some(21));
+    }
+
+    #[test]
+    fn optimal_moves_gives_up() {
+        let distances = distances(3, 2);
+        let (hardest, _) = distances.values().max_by_key(|(_, d)| *d).unwrap();
+        assert_eq!(hardest.optimal_moves(10), None);
+    }
+
+    #[test]
+    fn placement_moves_solve_boards() {
+        for (cols, rows) in [(2, 2), (3, 2), (2, 3), (4, 4), (5, 4), (8, 5)] {
+            for seed in 1..=3 {
+                let mut tiles = Tiles::new_shuffled(cols, rows, &mut Xorshift::seed(seed), 2_000);
+                let mut count = 0;
+                while let Some(m) = tiles.placement_move() {
+                    assert!(tiles.do_move(m));
+                    count += 1;
+                    assert!(count < 5_000, "{}x{} seed {}", cols, rows, seed);
+                }
+                assert!(tiles.is_won(), "{}x{} seed {}", cols, rows, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn repeated_hints_solve_a_4x4_board() {
+        for seed in 1..=2 {
+            let mut tiles = Tiles::new_shuffled(4, 4, &mut Xorshift::seed(seed), 512);
+            let mut hints = Hints::default();
+            let mut count = 0;
+            while let Some(m) = hints.next(&tiles, 20_000) {
+                assert!(tiles.do_move(m));
+                count += 1;
+                assert!(count < 1_000, "seed {}", seed);
+            }
+            assert!(tiles.is_won(), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn hints_follow_an_optimal_solution_when_found() {
+        for (tiles, distance) in distances(3, 2).values() {
+            let mut tiles = tiles.clone();
+            let mut hints = Hints::default();
+            let mut count = 0;
+            while let Some(m) = hints.next(&tiles, 100_000) {
+                assert!(tiles.do_move(m));
+                count += 1;
+            }
+            assert!(tiles.is_won());
+            assert_eq!(count, *distance);
+        }
+    }
+
+    #[test]
+    fn hints_do_not_search_again_after_giving_up() {
+        let (start, _) = distances(3, 2)
+            .into_values()
+            .find(|(_, distance)| *distance > 10)
+            .unwrap();
+        let mut tiles = start.clone();
+        let mut hints = Hints::default();
+        let m = hints.next(&tiles, 1).unwrap();
+        assert_eq!(Some(m), tiles.placement_move());
+        assert!(tiles.do_move(m));
+        // following the hints, even a generous budget does not bring the search back
+        assert_eq!(hints.next(&tiles, 100_000), tiles.placement_move());
+        assert!(hints.plan.is_empty());
+
+        // but a move of the player's own does
+        let mut tiles = start;
+        let m = MOVES
+            .into_iter()
+            .find(|&m| tiles.clone().do_move(m))
+            .unwrap();
+        assert!(tiles.do_move(m));
+        hints.next(&tiles, 100_000).unwrap();
+        assert!(!hints.plan.is_empty());
+    }
+
+    #[test]
+    fn slide_towards_every_position() {
+        let mut tiles = Tiles::new(4, 4);
+        // bring the empty slot to (1, 2)
+        for m in [Move::Up, Move::Up, Move::Left] {
+            assert!(tiles.do_move(m));
+        }
+        assert_eq!((tiles.empty_x, tiles.empty_y), (1, 2));
+
+        for y in 0..4 {
+            for x in 0..4 {
+                let slide = tiles.slide_towards(x, y);
+                if x != 1 && y != 2 || (x, y) == (1, 2) {
+                    assert_eq!(slide, None, "({}, {})", x, y);
+                    continue;
+                }
+                let (m, count) = slide.unwrap();
+                let expected = match (x, y) {
+                    (0, _) => Move::Right,
+                    (_, 2) => Move::Left,
+                    (_, 0 | 1) => Move::Down,
+                    _ => Move::Up,
+                };
+                assert_eq!(m, expected, "({}, {})", x, y);
+                assert_eq!(count as u32, x.abs_diff(1) as u32 + y.abs_diff(2) as u32);
+
+                // the empty slot ends up where the tile was,
+                // and the tile one step closer to where the empty slot was
+                let mut moved = tiles.clone();
+                let tile = moved.tiles[y as usize * 4 + x as usize];
+                for _ in 0..count {
+                    assert!(moved.do_move(m));
+                }
+                assert_eq!((moved.empty_x, moved.empty_y), (x, y));
+                let (dx, dy) = match m {
+                    Move::Left => (1, 0),
+                    Move::Right => (-1, 0),
+                    Move::Up => (0, 1),
+                    Move::Down => (0, -1),
+                };
+                let (tile_x, tile_y) = (x as i32 - dx, y as i32 - dy);
+                assert_eq!(moved.tiles[tile_y as usize * 4 + tile_x as usize], tile);
+            }
+        }
+    }
+}
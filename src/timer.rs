@@ -0,0 +1,199 @@
+//! Time keeping based on the BIOS tick count,
+//! plus a fast timer for animations
+//! (channel 0 of the PIT sped up, with a chained IRQ0 handler)
+use core::arch::asm;
+use core::ffi::c_void;
+use core::ptr::{addr_of, addr_of_mut, read_volatile, write_volatile};
+use dos_x::djgpp::pc::outportb;
+
+use crate::irq::{self, SegInfo};
+
+/// Number of BIOS ticks in ten seconds
+/// (the BIOS timer runs at about 18.2 Hz)
+const TICKS_PER_TEN_SECONDS: u32 = 182;
+
+/// Number of BIOS ticks in a day,
+/// after which the counter wraps around back to 0
+const TICKS_PER_DAY: u32 = 0x1800B0;
+
+/// Convert a number of BIOS ticks to whole seconds.
+pub fn ticks_to_seconds(ticks: u32) -> u32 {
+    ticks * 10 / TICKS_PER_TEN_SECONDS
+}
+
+/// Convert a number of seconds to BIOS ticks.
+pub fn seconds_to_ticks(seconds: u32) -> u32 {
+    seconds * TICKS_PER_TEN_SECONDS / 10
+}
+
+/// Address of the BIOS tick count in conventional memory
+/// (0040:006C)
+const BIOS_TICKS_ADDRESS: u32 = 0x46C;
+
+extern "C" {
+    fn dosmemget(offset: u32, length: usize, buffer: *mut c_void);
+}
+
+/// Get the current BIOS tick count (ticks since midnight).
+///
+/// The count is read straight from the BIOS data area
+/// rather than through INT 1Ah,
+/// which would clear the midnight flag before DOS gets to see it
+/// and keep the date from advancing.
+pub fn ticks() -> u32 {
+    let read = || {
+        let mut ticks: u32 = 0;
+        unsafe {
+            dosmemget(BIOS_TICKS_ADDRESS, 4, addr_of_mut!(ticks) as *mut c_void);
+        }
+        ticks
+    };
+    // the timer interrupt may update the count halfway through reading it
+    let mut ticks = read();
+    loop {
+        let again = read();
+        if again == ticks {
+            return ticks;
+        }
+        ticks = again;
+    }
+}
+
+/// Get the number of ticks elapsed between two tick counts,
+/// taking midnight rollover into account.
+pub fn ticks_between(start: u32, end: u32) -> u32 {
+    if end >= start {
+        end - start
+    } else {
+        end + TICKS_PER_DAY - start
+    }
+}
+
+/// Interrupt vector of IRQ0, raised by channel 0 of the PIT
+const TIMER_VECTOR: i32 = 0x08;
+
+/// How many times faster channel 0 of the PIT runs
+/// while the fast timer is installed
+const SPEEDUP: u32 = 16;
+
+/// Input frequency of the PIT in Hz
+const PIT_FREQUENCY: u64 = 1_193_182;
+
+/// Number of interrupts received from the sped up PIT
+static mut FAST_TICKS: u32 = 0;
+
+/// Whether the fast timer is in place
+static mut INSTALLED: bool = false;
+
+/// The interrupt handler which was there before
+static mut OLD_HANDLER: SegInfo = SegInfo::new();
+
+/// Our interrupt handler, wrapped to return with `iret`
+static mut NEW_HANDLER: SegInfo = SegInfo::new();
+
+/// Handle IRQ0, counting fast ticks.
+///
+/// Only one in every `SPEEDUP` interrupts is chained to the old handler,
+/// so that the BIOS clock keeps running at the usual rate.
+extern "C" fn timer_handler() {
+    unsafe {
+        let ticks = read_volatile(addr_of!(FAST_TICKS)).wrapping_add(1);
+        write_volatile(addr_of_mut!(FAST_TICKS), ticks);
+        if ticks % SPEEDUP == 0 {
+            // simulate an interrupt into the old handler,
+            // which also acknowledges it
+            // (only assembled for DOS, not for the unit tests on a 64-bit host)
+            #[cfg(target_arch = "x86")]
+            asm!(
+                "pushfd",
+                "call fword ptr [{0}]",
+                in(reg) addr_of!(OLD_HANDLER.pm_offset),
+            );
+        } else {
+            // end of interrupt
+            outportb(0x20, 0x20);
+        }
+    }
+}
+
+/// Set the divisor of channel 0 of the PIT
+/// (0 meaning 65536, the BIOS default).
+fn set_pit_divisor(divisor: u16) {
+    unsafe {
+        // channel 0, low and high byte, square wave
+        outportb(0x43, 0x36);
+        outportb(0x40, (divisor & 0xff) as u8);
+        outportb(0x40, (divisor >> 8) as u8);
+    }
+}
+
+/// Install the fast timer:
+/// speed up channel 0 of the PIT and hook IRQ0.
+///
+/// Everything is restored when the program exits.
+/// If the handler cannot be installed,
+/// [`millis`] falls back to the BIOS tick count.
+pub fn install() {
+    unsafe {
+        if INSTALLED {
+            return;
+        }
+        irq::lock_data(addr_of_mut!(FAST_TICKS));
+        irq::lock_data(addr_of_mut!(OLD_HANDLER));
+        irq::lock_code(timer_handler);
+
+        if !irq::hook(
+            TIMER_VECTOR,
+            timer_handler,
+            addr_of_mut!(OLD_HANDLER),
+            addr_of_mut!(NEW_HANDLER),
+        ) {
+            return;
+        }
+
+        asm!("cli");
+        set_pit_divisor((65536 / SPEEDUP) as u16);
+        INSTALLED = true;
+        asm!("sti");
+
+        irq::atexit(uninstall_at_exit);
+    }
+}
+
+extern "C" fn uninstall_at_exit() {
+    uninstall();
+}
+
+/// Restore the PIT and the original IRQ0 handler.
+fn uninstall() {
+    unsafe {
+        if !INSTALLED {
+            return;
+        }
+        asm!("cli");
+        set_pit_divisor(0);
+        INSTALLED = false;
+        asm!("sti");
+        irq::unhook(
+            TIMER_VECTOR,
+            addr_of_mut!(OLD_HANDLER),
+            addr_of_mut!(NEW_HANDLER),
+        );
+    }
+}
+
+/// Get a time stamp in milliseconds,
+/// for timing animations.
+///
+/// Only differences between time stamps are meaningful.
+pub fn millis() -> u32 {
+    unsafe {
+        if INSTALLED {
+            let ticks = read_volatile(addr_of!(FAST_TICKS)) as u64;
+            (ticks * 1000 * (65536 / SPEEDUP as u64) / PIT_FREQUENCY) as u32
+        } else {
+            // about 55 ms per BIOS tick
+            ticks().wrapping_mul(55)
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Transitions from the picture of a level to its scrambled board
+use alloc::vec::Vec;
+use tinyrand::RandRange;
+
+use crate::anim::{animate, PROGRESS_MAX};
+use crate::screen::{self, HEIGHT, WIDTH};
+
+/// Duration of a transition in milliseconds
+const TRANSITION_MILLIS: u32 = 1_000;
+
+/// How the scrambled board replaces the picture when a level starts
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Transition {
+    /// any of the others, chosen for each level
+    Random,
+    /// tiles appearing one by one
+    TileByTile,
+    /// pixels of the board appearing in random order
+    Dissolve,
+    /// the board sweeping in from the left
+    WipeHorizontal,
+    /// the board sweeping in from the top
+    WipeVertical,
+    /// every tile turning over
+    Flip,
+}
+
+impl Transition {
+    /// The name of the transition, as shown in the options
+    pub fn name(self) -> &'static str {
+        match self {
+            Transition::Random => "Random",
+            Transition::TileByTile => "Tile by tile",
+            Transition::Dissolve => "Dissolve",
+            Transition::WipeHorizontal => "Horizontal wipe",
+            Transition::WipeVertical => "Vertical wipe",
+            Transition::Flip => "Tile flip",
+        }
+    }
+
+    /// The transition after this one in the options
+    pub fn next(self) -> Self {
+        Transition::from_u8(self as u8 + 1).unwrap_or(Transition::Random)
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Transition::Random),
+            1 => Some(Transition::TileByTile),
+            2 => Some(Transition::Dissolve),
+            3 => Some(Transition::WipeHorizontal),
+            4 => Some(Transition::WipeVertical),
+            5 => Some(Transition::Flip),
+            _ => None,
+        }
+    }
+
+    /// Resolve a random transition into one of the others.
+    pub fn pick(self, rng: &mut impl RandRange<u16>) -> Self {
+        match self {
+            Transition::Random => Transition::from_u8(1 + rng.next_range(0..5) as u8)
+                .unwrap_or(Transition::TileByTile),
+            transition => transition,
+        }
+    }
+}
+
+/// Gradually replace what is on the screen (`from`)
+/// with a full screen image of the board (`to`).
+///
+/// `tiles` are the rectangles of the tiles on the screen
+/// (x, y, width, height), which only matter when flipping.
+/// Tile by tile transitions are drawn by the game itself,
+/// and do nothing here.
+pub fn run(transition: Transition, from: &[u8], to: &[u8], tiles: &[(u32, u32, u32, u32)]) {
+    match transition {
+        Transition::Random | Transition::TileByTile => {}
+        Transition::Dissolve => dissolve(to),
+        Transition::WipeHorizontal => animate(TRANSITION_MILLIS, |t| {
+            let width = WIDTH * t / PROGRESS_MAX;
+            screen::blit_rect(to, (WIDTH, HEIGHT), (0, 0, width, HEIGHT), (0, 0));
+            screen::present();
+        }),
+        Transition::WipeVertical => animate(TRANSITION_MILLIS, |t| {
+            let height = HEIGHT * t / PROGRESS_MAX;
+            screen::blit_rect(to, (WIDTH, HEIGHT), (0, 0, WIDTH, height), (0, 0));
+            screen::present();
+        }),
+        Transition::Flip => flip(from, to, tiles),
+    }
+}
+
+/// Reveal the pixels of the new image in a pseudo-random order,
+/// following a linear feedback shift register
+/// which goes through every 16-bit value but zero.
+fn dissolve(to: &[u8]) {
+    let total = (WIDTH * HEIGHT) as usize;
+    let mut lfsr: u16 = 1;
+    let mut revealed = 0;
+    animate(TRANSITION_MILLIS, |t| {
+        let target = total * t as usize / PROGRESS_MAX as usize;
+        let mut offsets = Vec::with_capacity(target - revealed);
+        while revealed < target {
+            let offset = lfsr as usize - 1;
+            lfsr = (lfsr >> 1) ^ (0u16.wrapping_sub(lfsr & 1) & 0xB400);
+            if offset < total {
+                offsets.push(offset);
+                revealed += 1;
+            }
+        }
+        screen::copy_pixels(to, offsets);
+        screen::present();
+    });
+}
+
+/// Turn every tile over around its horizontal axis:
+/// the old picture shrinks into a line
+/// and the new one grows out of it.
+fn flip(from: &[u8], to: &[u8], tiles: &[(u32, u32, u32, u32)]) {
+    animate(TRANSITION_MILLIS, |t| {
+        // the first half shows the old side, the second half the new one
+        let (side, scale) = if t < PROGRESS_MAX / 2 {
+            (from, PROGRESS_MAX - t * 2)
+        } else {
+            (to, t * 2 - PROGRESS_MAX)
+        };
+        for &(x, y, width, height) in tiles {
+            let scaled_height = height * scale / PROGRESS_MAX;
+            let top = y + (height - scaled_height) / 2;
+            screen::draw_rect(x as i32, y as i32, width, height, 255);
+            for row in 0..scaled_height {
+                let source_y = y + row * height / scaled_height;
+                screen::blit_rect(
+                    side,
+                    (WIDTH, HEIGHT),
+                    (x, source_y, width, 1),
+                    (x as i32, (top + row) as i32),
+                );
+            }
+        }
+        screen::present();
+    });
+}